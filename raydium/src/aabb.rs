@@ -0,0 +1,114 @@
+use crate::{ray::Ray, vec::Vec3};
+
+/// Axis-aligned bounding box stored as its min and max corners.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Self {
+            min: a.min.min(&b.min),
+            max: a.max.max(&b.max),
+        }
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Self {
+        Self {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    /// Widens any axis thinner than `delta` so flat primitives (axis-aligned
+    /// triangles and quads) still produce a box that rays can hit.
+    pub fn pad(&self, delta: f64) -> Self {
+        let d = self.extent();
+        let widen = |axis: usize| if d[axis] < delta { delta / 2.0 } else { 0.0 };
+        let w = Vec3(widen(0), widen(1), widen(2));
+        Self {
+            min: self.min - w,
+            max: self.max + w,
+        }
+    }
+
+    pub fn contains(&self, point: &Vec3) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max).mul_scalar(0.5)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.extent();
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.extent();
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test against a ray whose reciprocal direction has already been
+    /// computed, for when the same ray is tested against many boxes.
+    pub fn hit_inv(&self, ray: &RayInv, t_min: f64, t_max: f64) -> bool {
+        let t0 = (self.min - ray.origin) * ray.inv_dir;
+        let t1 = (self.max - ray.origin) * ray.inv_dir;
+        let near = t0.min(&t1);
+        let far = t0.max(&t1);
+        let t_enter = f64::max(t_min, f64::max(near.x(), f64::max(near.y(), near.z())));
+        let t_exit = f64::min(t_max, f64::min(far.x(), f64::min(far.y(), far.z())));
+        t_enter <= t_exit
+    }
+
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = f64::max(t0, t_min);
+            t_max = f64::min(t1, t_max);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A ray with its reciprocal direction precomputed for slab tests.
+#[derive(Debug, Copy, Clone)]
+pub struct RayInv {
+    pub origin: Vec3,
+    pub inv_dir: Vec3,
+}
+
+impl From<&Ray> for RayInv {
+    fn from(ray: &Ray) -> Self {
+        let d = ray.direction;
+        Self {
+            origin: ray.origin,
+            inv_dir: Vec3(1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z()),
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+use image::DynamicImage;
+use rad::{
+    film::{Film, FilmOrigin},
+    integrator::IntegratorDesc,
+    render::{AdaptiveSampling, CancelToken, RayRenderer},
+    sampler::SamplerKind,
+    scene::SceneDesc,
+    world::Camera,
+};
+
+const USAGE: &str = "\
+Usage: raydium-cli <scene.toml> [options]
+
+Renders a scene file without opening a window.
+
+Options:
+  -o, --output <path>    Output image, format chosen by extension [default: render.png]
+      --width <px>       Image width (height follows the camera aspect ratio
+                         unless --height is also given)
+      --height <px>      Image height
+  -s, --spp <n>          Samples per pixel
+  -d, --max-depth <n>    Maximum scatter depth
+      --roulette-depth <n>
+                         Bounces before Russian roulette may end a path
+  -i, --integrator <name>
+                         path, naive_path, whitted, ambient_occlusion,
+                         normals, albedo or depth
+      --sampler <name>   independent, stratified, halton, sobol or
+                         blue_noise
+      --adaptive <t>     Stop sampling pixels once their relative error is
+                         below t, spending the samples on noisier ones
+      --sample-map <path>
+                         Also write how many samples each pixel took
+      --film <path>      Add to the samples saved in this film, if it
+                         exists, and save them there afterwards, even if
+                         the render is interrupted with Ctrl-C
+  -j, --threads <n>      Worker threads [default: one per core]
+      --seed <n>         Random seed
+  -h, --help             Print this help
+";
+
+#[derive(Debug, Default)]
+struct Args {
+    scene: PathBuf,
+    output: PathBuf,
+    width: Option<u32>,
+    height: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_scatter_depth: Option<u32>,
+    roulette_depth: Option<u32>,
+    integrator: Option<IntegratorDesc>,
+    sampler: Option<SamplerKind>,
+    adaptive: Option<f64>,
+    sample_map: Option<PathBuf>,
+    film: Option<PathBuf>,
+    threads: Option<usize>,
+    seed: Option<u64>,
+}
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> anyhow::Result<Option<Args>> {
+    fn value<T: std::str::FromStr>(
+        flag: &str,
+        argv: &mut impl Iterator<Item = String>,
+    ) -> anyhow::Result<T> {
+        let v = argv
+            .next()
+            .ok_or_else(|| anyhow!("{} expects a value", flag))?;
+        v.parse()
+            .map_err(|_| anyhow!("invalid value `{}` for {}", v, flag))
+    }
+
+    let mut args = Args {
+        output: PathBuf::from("render.png"),
+        ..Default::default()
+    };
+    let mut scene = None;
+
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => args.output = value(&arg, &mut argv)?,
+            "--width" => args.width = Some(value(&arg, &mut argv)?),
+            "--height" => args.height = Some(value(&arg, &mut argv)?),
+            "-s" | "--spp" => args.samples_per_pixel = Some(value(&arg, &mut argv)?),
+            "-d" | "--max-depth" => args.max_scatter_depth = Some(value(&arg, &mut argv)?),
+            "--roulette-depth" => args.roulette_depth = Some(value(&arg, &mut argv)?),
+            "-i" | "--integrator" => args.integrator = Some(value(&arg, &mut argv)?),
+            "--sampler" => args.sampler = Some(value(&arg, &mut argv)?),
+            "--adaptive" => args.adaptive = Some(value(&arg, &mut argv)?),
+            "--sample-map" => args.sample_map = Some(value(&arg, &mut argv)?),
+            "--film" => args.film = Some(value(&arg, &mut argv)?),
+            "-j" | "--threads" => args.threads = Some(value(&arg, &mut argv)?),
+            "--seed" => args.seed = Some(value(&arg, &mut argv)?),
+            flag if flag.starts_with('-') => bail!("unknown option `{}`", flag),
+            path => {
+                if scene.replace(PathBuf::from(path)).is_some() {
+                    bail!("only one scene file may be given");
+                }
+            }
+        }
+    }
+
+    args.scene = scene.ok_or_else(|| anyhow!("missing scene file"))?;
+    Ok(Some(args))
+}
+
+/// Replaces the scene's render settings with the ones given on the command
+/// line, and checks the result.
+fn apply_overrides(desc: &mut SceneDesc, args: &Args) -> anyhow::Result<()> {
+    let render = &mut desc.render;
+    if let Some(width) = args.width {
+        render.width = width;
+        if args.height.is_none() {
+            render.height = ((width as f64 / desc.camera.aspect_ratio) as u32).max(1);
+        }
+    }
+    if let Some(height) = args.height {
+        render.height = height;
+    }
+    if let Some(spp) = args.samples_per_pixel {
+        render.samples_per_pixel = spp;
+    }
+    if let Some(depth) = args.max_scatter_depth {
+        render.max_scatter_depth = depth;
+    }
+    if let Some(depth) = args.roulette_depth {
+        render.roulette_depth = depth;
+    }
+    if let Some(integrator) = args.integrator {
+        render.integrator = integrator;
+    }
+    if let Some(sampler) = args.sampler {
+        render.sampler = sampler;
+    }
+    if let Some(threshold) = args.adaptive {
+        // Without a `[render.adaptive]` table, keep the default minimum
+        // within a small `--spp`.
+        let adaptive = render.adaptive.unwrap_or_else(|| {
+            let default = AdaptiveSampling::default();
+            AdaptiveSampling {
+                min_samples: default.min_samples.min(render.samples_per_pixel),
+                ..default
+            }
+        });
+        render.adaptive = Some(AdaptiveSampling {
+            threshold,
+            ..adaptive
+        });
+    }
+    if let Some(seed) = args.seed {
+        render.seed = seed;
+    }
+    if render.width == 0 || render.height == 0 || render.samples_per_pixel == 0 {
+        bail!("width, height and samples per pixel must be non-zero");
+    }
+    if let Some(adaptive) = render.adaptive {
+        if render.samples_per_pixel < 2 {
+            bail!("adaptive sampling needs at least 2 samples per pixel (--spp)");
+        }
+        if let Err(msg) = adaptive.validate(render.samples_per_pixel) {
+            bail!("{}", msg);
+        }
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("failed to configure worker threads")?;
+    }
+
+    let start = Instant::now();
+    let mut desc = SceneDesc::load(&args.scene)?;
+
+    apply_overrides(&mut desc, &args)?;
+
+    let base_dir = args.scene.parent().unwrap_or(Path::new(""));
+    let scene = desc
+        .build(base_dir)
+        .with_context(|| format!("failed to build scene {}", args.scene.display()))?;
+    eprintln!("Loaded {} in {:.2?}", args.scene.display(), start.elapsed());
+    eprintln!(
+        "Rendering {}x{} at {} spp with {} and the {} sampler, max depth {}, seed {}, {} threads",
+        scene.size.width,
+        scene.size.height,
+        scene.camera.samples_per_pixel,
+        scene.integrator.name(),
+        scene.camera.sampler.name(),
+        scene.camera.max_scatter_depth,
+        scene.camera.seed,
+        rayon::current_num_threads()
+    );
+
+    let renderer = RayRenderer::new(Camera::with_info(&scene.camera))
+        .with_integrator(scene.integrator.build())
+        .with_adaptive_sampling(scene.adaptive);
+    let origin = FilmOrigin::new(renderer.camera(), desc.film_hash()?);
+    let mut film = match &args.film {
+        Some(path) if path.exists() => {
+            let film = Film::load(path)?;
+            let saved = film.origin();
+            if (film.size().width, film.size().height) != (scene.size.width, scene.size.height) {
+                bail!(
+                    "{} is {}x{}, not {}x{}",
+                    path.display(),
+                    film.size().width,
+                    film.size().height,
+                    scene.size.width,
+                    scene.size.height
+                );
+            }
+            if saved.scene != origin.scene {
+                bail!(
+                    "{} was rendered from a different scene or render settings",
+                    path.display()
+                );
+            }
+            if saved.seed != origin.seed {
+                bail!(
+                    "{} was rendered with seed {}, not {}",
+                    path.display(),
+                    saved.seed,
+                    origin.seed
+                );
+            }
+            if saved.sampler != origin.sampler {
+                bail!(
+                    "{} was rendered with the {} sampler, not {}",
+                    path.display(),
+                    saved.sampler.name(),
+                    origin.sampler.name()
+                );
+            }
+            eprintln!(
+                "Resuming {} with {} samples",
+                path.display(),
+                film.total_samples()
+            );
+            film
+        }
+        _ => Film::new(scene.size, origin),
+    };
+    // Ctrl-C stops the render between rows, and what it got to is still
+    // written out; a second one exits straight away.
+    let cancel = CancelToken::new();
+    let renderer = renderer.with_cancel_token(cancel.clone());
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        handler_cancel.cancel();
+    })
+    .context("failed to install the Ctrl-C handler")?;
+
+    let render_start = Instant::now();
+    // Redraw when the percentage changes, or every second for the ETA.
+    let last_shown = Mutex::new(None::<(u32, Instant)>);
+    renderer.render_to_film(&scene.world, &mut film, &|progress| {
+        let percent = (progress.fraction() * 100.0) as u32;
+        let mut last = last_shown.lock().unwrap();
+        if let Some((shown, at)) = *last {
+            if shown == percent && at.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+        }
+        *last = Some((percent, Instant::now()));
+        let eta = progress
+            .eta
+            .map_or_else(|| "-".into(), |eta| format!("{:.0?}", eta));
+        eprint!(
+            "\rProgress: {:3}% | pass {} | row {}/{} | {} rays | ETA {}    ",
+            percent,
+            progress.pass,
+            progress.rows_done,
+            progress.rows,
+            si_count(progress.rays),
+            eta
+        );
+        let _ = std::io::stderr().flush();
+    });
+    let interrupted = cancel.is_cancelled();
+    let verb = if interrupted {
+        "Interrupted"
+    } else {
+        "Rendered"
+    };
+    eprintln!("\r{} in {:.2?}{:60}", verb, render_start.elapsed(), "");
+    if scene.adaptive.is_some() {
+        eprintln!(
+            "Took {:.1} samples per pixel on average",
+            film.total_samples() as f64 / film.pixels().len() as f64
+        );
+    }
+
+    // Drop alpha so formats without it (JPEG, PPM) can be written too.
+    DynamicImage::ImageRgba8(film.to_image())
+        .to_rgb8()
+        .save(&args.output)
+        .with_context(|| format!("failed to write {}", args.output.display()))?;
+    eprintln!(
+        "Wrote {} (total {:.2?})",
+        args.output.display(),
+        start.elapsed()
+    );
+    if let Some(path) = &args.sample_map {
+        film.sample_count_image()
+            .save(path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!("Wrote sample counts to {}", path.display());
+    }
+    if let Some(path) = &args.film {
+        film.save(path)?;
+        eprintln!("Saved film to {}", path.display());
+    }
+    if interrupted {
+        bail!(
+            "render interrupted after {:.1} samples per pixel on average",
+            film.total_samples() as f64 / film.pixels().len() as f64
+        );
+    }
+    Ok(())
+}
+
+/// `n` shortened with a k, M or G suffix.
+fn si_count(n: u64) -> String {
+    let units = [(1e9, "G"), (1e6, "M"), (1e3, "k")];
+    match units.iter().find(|(scale, _)| n as f64 >= *scale) {
+        Some((scale, suffix)) => format!("{:.1}{}", n as f64 / scale, suffix),
+        None => n.to_string(),
+    }
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Option<Args>> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    fn parse_err(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("invalid arguments accepted: {:?}", args),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn options_are_read_around_the_scene_path() {
+        let args = parse(&["-s", "16", "scene.toml", "--width", "320", "-o", "out.exr"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.scene, PathBuf::from("scene.toml"));
+        assert_eq!(args.output, PathBuf::from("out.exr"));
+        assert_eq!(args.samples_per_pixel, Some(16));
+        assert_eq!(args.width, Some(320));
+        assert_eq!(args.height, None);
+    }
+
+    #[test]
+    fn unknown_option_is_rejected() {
+        assert_eq!(
+            parse_err(&["scene.toml", "--frobnicate"]),
+            "unknown option `--frobnicate`"
+        );
+    }
+
+    #[test]
+    fn option_without_value_is_rejected() {
+        assert_eq!(parse_err(&["scene.toml", "--spp"]), "--spp expects a value");
+        assert_eq!(
+            parse_err(&["scene.toml", "--spp", "many"]),
+            "invalid value `many` for --spp"
+        );
+    }
+
+    #[test]
+    fn only_one_scene_path_is_accepted() {
+        assert_eq!(
+            parse_err(&["a.toml", "b.toml"]),
+            "only one scene file may be given"
+        );
+        assert_eq!(parse_err(&["--spp", "4"]), "missing scene file");
+    }
+
+    #[test]
+    fn help_wins_over_everything_else() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["scene.toml", "-h", "--bogus"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn adaptive_sampling_needs_two_samples_per_pixel() {
+        let scene = "[render]\nsamples_per_pixel = 64\n";
+        let mut desc = SceneDesc::parse(scene).unwrap();
+        let args = parse(&["scene.toml", "-s", "1", "--adaptive", "0.05"])
+            .unwrap()
+            .unwrap();
+        let Err(e) = apply_overrides(&mut desc, &args) else {
+            panic!("--adaptive accepted with one sample per pixel");
+        };
+        assert_eq!(
+            e.to_string(),
+            "adaptive sampling needs at least 2 samples per pixel (--spp)"
+        );
+
+        // A small --spp lowers the default minimum to fit.
+        let mut desc = SceneDesc::parse(scene).unwrap();
+        let args = parse(&["scene.toml", "-s", "4", "--adaptive", "0.05"])
+            .unwrap()
+            .unwrap();
+        apply_overrides(&mut desc, &args).unwrap();
+        assert_eq!(desc.render.adaptive.unwrap().min_samples, 4);
+    }
+}
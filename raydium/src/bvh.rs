@@ -0,0 +1,430 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::{Aabb, RayInv},
+    ray::{HitList, HitRecord, Hittable, Ray},
+    vec::Vec3,
+};
+
+const NUM_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECT_COST: f64 = 1.0;
+/// Nodes this deep are split at the median, which takes at most 31 more
+/// levels for up to 2^31 objects, so traversal never holds more than
+/// `STACK_SIZE` nodes.
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+#[derive(Debug, Copy, Clone)]
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    },
+    /// The first child always directly follows its parent in the node array.
+    Interior {
+        bounds: Aabb,
+        second: usize,
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    const fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } => bounds,
+            BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+struct BuildPrim<T: ?Sized> {
+    bounds: Aabb,
+    centroid: Vec3,
+    object: Arc<T>,
+}
+
+/// Bounding volume hierarchy over a set of objects, built with a binned
+/// surface area heuristic and stored as a flat array of nodes.
+///
+/// Objects without a bounding box are kept aside and tested linearly.
+pub struct Bvh<T: Hittable + Send + Sync + ?Sized> {
+    nodes: Vec<BvhNode>,
+    objects: Vec<Arc<T>>,
+    unbounded: Vec<Arc<T>>,
+}
+
+impl<T> Bvh<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    pub fn new(objects: Vec<Arc<T>>, time0: f64, time1: f64) -> Self {
+        let mut prims = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box(time0, time1) {
+                Some(bounds) => prims.push(BuildPrim {
+                    bounds,
+                    centroid: bounds.centroid(),
+                    object,
+                }),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(prims.len() * 2);
+        let mut ordered = Vec::with_capacity(prims.len());
+        if !prims.is_empty() {
+            build_recursive(&mut prims, &mut nodes, &mut ordered, 0);
+        }
+
+        Self {
+            nodes,
+            objects: ordered,
+            unbounded,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn from_list(list: &HitList<T>, time0: f64, time1: f64) -> Self {
+        Self::new(list.0.clone(), time0, time1)
+    }
+}
+
+fn build_recursive<T: ?Sized>(
+    prims: &mut [BuildPrim<T>],
+    nodes: &mut Vec<BvhNode>,
+    ordered: &mut Vec<Arc<T>>,
+    depth: usize,
+) -> usize {
+    let bounds = prims
+        .iter()
+        .skip(1)
+        .fold(prims[0].bounds, |acc, p| Aabb::surrounding(&acc, &p.bounds));
+
+    let index = nodes.len();
+    let make_leaf =
+        |nodes: &mut Vec<BvhNode>, ordered: &mut Vec<Arc<T>>, prims: &[BuildPrim<T>]| {
+            nodes.push(BvhNode::Leaf {
+                bounds,
+                first: ordered.len(),
+                count: prims.len(),
+            });
+            ordered.extend(prims.iter().map(|p| p.object.clone()));
+            index
+        };
+
+    if prims.len() == 1 {
+        return make_leaf(nodes, ordered, prims);
+    }
+
+    let centroid_bounds = prims
+        .iter()
+        .fold(Aabb::new(prims[0].centroid, prims[0].centroid), |acc, p| {
+            acc.grow(&p.centroid)
+        });
+    let axis = centroid_bounds.longest_axis();
+    let cmin = centroid_bounds.min[axis];
+    let cextent = centroid_bounds.max[axis] - cmin;
+
+    // Every centroid coincides, so no spatial split can separate them.
+    if cextent <= 0.0 {
+        return make_leaf(nodes, ordered, prims);
+    }
+
+    // Past `MAX_SAH_DEPTH` the split is always at the median, bounding the
+    // depth of the tree so `hit` can traverse it with a fixed-size stack.
+    let mut mid = if depth < MAX_SAH_DEPTH {
+        match sah_split(prims, &bounds, axis, cmin, cextent) {
+            Some(mid) => mid,
+            None => return make_leaf(nodes, ordered, prims),
+        }
+    } else {
+        0
+    };
+    if mid == 0 || mid == prims.len() {
+        mid = prims.len() / 2;
+        prims.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    }
+
+    // Placeholder until the second child's index is known.
+    nodes.push(BvhNode::Leaf {
+        bounds,
+        first: 0,
+        count: 0,
+    });
+    let (left, right) = prims.split_at_mut(mid);
+    build_recursive(left, nodes, ordered, depth + 1);
+    let second = build_recursive(right, nodes, ordered, depth + 1);
+    nodes[index] = BvhNode::Interior {
+        bounds,
+        second,
+        axis,
+    };
+    index
+}
+
+/// Partitions `prims` along `axis` where the binned surface area heuristic
+/// says to split, returning the size of the first half, or `None` if they
+/// are cheaper to test as one leaf.
+fn sah_split<T: ?Sized>(
+    prims: &mut [BuildPrim<T>],
+    bounds: &Aabb,
+    axis: usize,
+    cmin: f64,
+    cextent: f64,
+) -> Option<usize> {
+    let bin_of = |p: &BuildPrim<T>| {
+        let b = ((p.centroid[axis] - cmin) / cextent * NUM_BINS as f64) as usize;
+        b.min(NUM_BINS - 1)
+    };
+
+    let mut bin_counts = [0usize; NUM_BINS];
+    let mut bin_bounds: [Option<Aabb>; NUM_BINS] = [None; NUM_BINS];
+    for p in prims.iter() {
+        let b = bin_of(p);
+        bin_counts[b] += 1;
+        bin_bounds[b] = Some(match bin_bounds[b] {
+            Some(bb) => Aabb::surrounding(&bb, &p.bounds),
+            None => p.bounds,
+        });
+    }
+
+    // Sweep from the right so each split's right-hand area/count is known
+    // while sweeping from the left. Costs are kept pre-multiplied by the
+    // parent's surface area to stay well-defined for flat bounds.
+    let mut right_costs = [0.0; NUM_BINS];
+    {
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        for i in (1..NUM_BINS).rev() {
+            acc = union_opt(acc, bin_bounds[i]);
+            count += bin_counts[i];
+            right_costs[i] = acc.map_or(0.0, |a| a.surface_area()) * count as f64;
+        }
+    }
+
+    let mut best_split = 0;
+    let mut best_cost = f64::INFINITY;
+    {
+        let mut acc: Option<Aabb> = None;
+        let mut count = 0;
+        for i in 0..NUM_BINS - 1 {
+            acc = union_opt(acc, bin_bounds[i]);
+            count += bin_counts[i];
+            let left_cost = acc.map_or(0.0, |a| a.surface_area()) * count as f64;
+            let cost = TRAVERSAL_COST * bounds.surface_area()
+                + INTERSECT_COST * (left_cost + right_costs[i + 1]);
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = i;
+            }
+        }
+    }
+
+    let leaf_cost = INTERSECT_COST * prims.len() as f64 * bounds.surface_area();
+    if prims.len() <= MAX_LEAF_SIZE && leaf_cost <= best_cost {
+        return None;
+    }
+
+    Some(partition(prims, |p| bin_of(p) <= best_split))
+}
+
+fn union_opt(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::surrounding(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl<T> Hittable for Bvh<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = t_max;
+        let mut result = None;
+
+        for object in self.unbounded.iter() {
+            if let Some(hit) = object.hit(ray, t_min, closest) {
+                closest = hit.t;
+                result = Some(hit);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return result;
+        }
+
+        let inv = RayInv::from(ray);
+        let mut stack = [0u32; STACK_SIZE];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let index = stack[len] as usize;
+            let node = &self.nodes[index];
+            if !node.bounds().hit_inv(&inv, t_min, closest) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { first, count, .. } => {
+                    for object in self.objects[first..first + count].iter() {
+                        if let Some(hit) = object.hit(ray, t_min, closest) {
+                            closest = hit.t;
+                            result = Some(hit);
+                        }
+                    }
+                }
+                BvhNode::Interior { second, axis, .. } => {
+                    // Visit the child nearer along the split axis first.
+                    let (near, far) = if ray.direction[axis] < 0.0 {
+                        (second, index + 1)
+                    } else {
+                        (index + 1, second)
+                    };
+                    stack[len] = far as u32;
+                    stack[len + 1] = near as u32;
+                    len += 2;
+                }
+            }
+        }
+
+        result
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|n| *n.bounds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{geom::Sphere, material::Lambertian};
+
+    fn sphere(center: Vec3, radius: f64) -> Arc<Sphere> {
+        // A material per sphere tells hits on different spheres apart.
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(material, center, radius))
+    }
+
+    fn random_point(rng: &mut StdRng, extent: f64) -> Vec3 {
+        Vec3(
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        )
+    }
+
+    /// Fires random rays into `objects` through both a `Bvh` and a plain
+    /// `HitList` and checks they find the same nearest hit.
+    fn assert_matches_list(objects: Vec<Arc<Sphere>>, extent: f64) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let list = HitList(objects.clone());
+        let bvh = Bvh::new(objects, 0.0, 0.0);
+        for _ in 0..2000 {
+            let origin = random_point(&mut rng, 2.0 * extent);
+            let target = random_point(&mut rng, extent);
+            let ray = Ray::new(origin, target - origin);
+            match (
+                bvh.hit(&ray, 0.001, f64::INFINITY),
+                list.hit(&ray, 0.001, f64::INFINITY),
+            ) {
+                (None, None) => {}
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.t, b.t);
+                    assert!(Arc::ptr_eq(&a.material, &b.material));
+                }
+                (a, b) => panic!(
+                    "bvh hit at {:?}, list at {:?}",
+                    a.map(|h| h.t),
+                    b.map(|h| h.t)
+                ),
+            }
+        }
+    }
+
+    /// Levels below node `index`.
+    fn depth<T: Hittable + Send + Sync + ?Sized>(bvh: &Bvh<T>, index: usize) -> usize {
+        match bvh.nodes[index] {
+            BvhNode::Leaf { .. } => 0,
+            BvhNode::Interior { second, .. } => 1 + depth(bvh, index + 1).max(depth(bvh, second)),
+        }
+    }
+
+    #[test]
+    fn random_rays_hit_what_a_list_hits() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let objects = (0..500)
+            .map(|_| sphere(random_point(&mut rng, 10.0), rng.gen_range(0.1..1.0)))
+            .collect();
+        assert_matches_list(objects, 10.0);
+    }
+
+    #[test]
+    fn empty_bvh_hits_nothing() {
+        let bvh: Bvh<Sphere> = Bvh::new(Vec::new(), 0.0, 0.0);
+        assert!(bvh.is_empty());
+        assert!(bvh.bounding_box(0.0, 0.0).is_none());
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert!(bvh.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn single_object() {
+        assert_matches_list(vec![sphere(Vec3(0.0, 0.0, 0.0), 1.0)], 2.0);
+    }
+
+    #[test]
+    fn degenerate_bounds() {
+        // Shared centres can't be split, spheres on a line have flat
+        // centroid bounds, and a zero radius gives a box with no volume.
+        let mut objects: Vec<_> = (1..20)
+            .map(|i| sphere(Vec3(0.0, 0.0, 0.0), i as f64 * 0.1))
+            .collect();
+        objects.extend((0..20).map(|i| sphere(Vec3(i as f64 - 10.0, 3.0, 0.0), 0.4)));
+        objects.push(sphere(Vec3(0.0, -3.0, 0.0), 0.0));
+        assert_matches_list(objects, 10.0);
+    }
+
+    #[test]
+    fn unbalanced_trees_stay_within_the_traversal_stack() {
+        // Each sphere dwarfs all the ones before it, so every SAH split
+        // peels off a single sphere.
+        let objects = (0..160)
+            .map(|i| {
+                let scale = 3f64.powi(i);
+                sphere(Vec3(scale, 0.0, 0.0), 0.2 * scale)
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(objects.clone(), 0.0, 0.0);
+        assert!(depth(&bvh, 0) < STACK_SIZE);
+        assert_matches_list(objects, 10.0);
+    }
+}
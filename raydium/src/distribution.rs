@@ -0,0 +1,197 @@
+/// Piecewise-constant 1D distribution over [0, 1), sampled by inverting its
+/// CDF.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// `func` holds the (non-negative) value of each of its equally sized
+    /// segments. An all-zero function is sampled uniformly.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one segment");
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f / n as f64);
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n as f64
+            };
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    /// Average value of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform `u` in [0, 1) to `(x, pdf, segment)`.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let segment = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.len() - 1);
+        let width = self.cdf[segment + 1] - self.cdf[segment];
+        let du = if width > 0.0 {
+            (u - self.cdf[segment]) / width
+        } else {
+            0.0
+        };
+        let pdf = if self.integral > 0.0 {
+            self.func[segment] / self.integral
+        } else {
+            1.0
+        };
+        ((segment as f64 + du) / self.len() as f64, pdf, segment)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.integral > 0.0 {
+            self.func[self.segment(x)] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    fn segment(&self, x: f64) -> usize {
+        ((x * self.len() as f64) as usize).min(self.len() - 1)
+    }
+}
+
+/// Piecewise-constant 2D distribution over [0, 1)², sampled as a marginal
+/// over rows followed by a conditional within the chosen row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` is row-major with `width` columns.
+    pub fn new(func: &[f64], width: usize) -> Self {
+        assert!(
+            width > 0 && func.len().is_multiple_of(width),
+            "distribution of {} values is not a whole number of rows of {}",
+            func.len(),
+            width
+        );
+        let conditional: Vec<_> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// Maps uniform `(u0, u1)` to a point `(u, v)` and its density.
+    pub fn sample(&self, u0: f64, u1: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = &self.conditional[self.marginal.segment(v)];
+        if self.marginal.integral > 0.0 {
+            row.func[row.segment(u)] / self.marginal.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 100_000;
+
+    /// Checks that samples land in each segment as often as `pdf` says and
+    /// that `sample` reports the same density as `pdf`.
+    fn check_1d(dist: &Distribution1D) {
+        let mut counts = vec![0usize; dist.len()];
+        for i in 0..N {
+            let (x, pdf, segment) = dist.sample((i as f64 + 0.5) / N as f64);
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(segment, dist.segment(x));
+            assert_eq!(pdf, dist.pdf(x));
+            counts[segment] += 1;
+        }
+        for (segment, count) in counts.iter().enumerate() {
+            let x = (segment as f64 + 0.5) / dist.len() as f64;
+            let expected = dist.pdf(x) / dist.len() as f64;
+            let got = *count as f64 / N as f64;
+            assert!(
+                (got - expected).abs() < 1e-3,
+                "segment {} sampled {} of the time, pdf says {}",
+                segment,
+                got,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn samples_1d_follow_the_pdf() {
+        check_1d(&Distribution1D::new(vec![1.0, 0.0, 3.0, 0.5, 2.0]));
+        check_1d(&Distribution1D::new(vec![4.0]));
+        // All zero falls back to uniform.
+        check_1d(&Distribution1D::new(vec![0.0; 4]));
+    }
+
+    #[test]
+    fn samples_2d_follow_the_pdf() {
+        let func = [1.0, 2.0, 0.0, 4.0, 0.5, 0.0, 0.0, 1.0, 3.0];
+        let dist = Distribution2D::new(&func, 3);
+        let total: f64 = func.iter().sum();
+        let side = 300;
+        let mut counts = [0usize; 9];
+        for i in 0..side {
+            for j in 0..side {
+                let u0 = (i as f64 + 0.5) / side as f64;
+                let u1 = (j as f64 + 0.5) / side as f64;
+                let ((u, v), pdf) = dist.sample(u0, u1);
+                assert!((pdf - dist.pdf(u, v)).abs() < 1e-12);
+                counts[(v * 3.0) as usize * 3 + (u * 3.0) as usize] += 1;
+            }
+        }
+        for (cell, count) in counts.iter().enumerate() {
+            let got = *count as f64 / (side * side) as f64;
+            let expected = func[cell] / total;
+            // The stratified grid quantizes each axis to 1/side.
+            assert!(
+                (got - expected).abs() < 2.0 / side as f64,
+                "cell {} sampled {} of the time, expected {}",
+                cell,
+                got,
+                expected
+            );
+            // Density over a cell of area 1/9.
+            let (u, v) = ((cell % 3) as f64 / 3.0 + 0.1, (cell / 3) as f64 / 3.0 + 0.1);
+            assert!((dist.pdf(u, v) - expected * 9.0).abs() < 1e-12);
+        }
+    }
+}
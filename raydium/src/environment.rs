@@ -0,0 +1,464 @@
+use std::{
+    f64::consts::PI,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    distribution::Distribution2D,
+    math::radians,
+    texture::load_linear_rgb,
+    vec::{Color, Vec3},
+};
+
+/// Radiance arriving from infinitely far away, seen by rays that escape the
+/// scene. Since paths are lit by whatever they escape into, this is also the
+/// scene's sky lighting.
+pub trait Environment: Send + Sync {
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    /// Picks a direction towards the environment from uniform `u0` and `u1`,
+    /// favouring bright regions. `None` means the environment has no
+    /// importance sampling and is only found by rays that escape by chance.
+    fn sample_direction(&self, _u0: f64, _u1: f64) -> Option<Vec3> {
+        None
+    }
+
+    /// Solid angle density with which `sample_direction` picks `direction`.
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+}
+
+pub struct SolidColor(pub Color);
+
+impl Environment for SolidColor {
+    fn radiance(&self, _direction: &Vec3) -> Color {
+        self.0
+    }
+}
+
+/// Vertical blend from `horizon` (looking down) to `zenith` (looking up).
+pub struct GradientSky {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for GradientSky {
+    fn default() -> Self {
+        Self {
+            horizon: Color::WHITE,
+            zenith: Vec3(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for GradientSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let dir = direction.normalize();
+        let t = 0.5 * (dir.y() + 1.0);
+        Vec3::lerp(&self.horizon, &self.zenith, t)
+    }
+}
+
+/// Zenith luminance (in kcd/m²) that maps to 1.0 at `intensity` 1.
+const SKY_LUMINANCE_SCALE: f64 = 25.0;
+
+/// Preetham et al. "A Practical Analytic Model for Daylight" (1999) clear sky.
+///
+/// The sky darkens and reddens as the sun approaches the horizon; `intensity`
+/// scales the result. Directions below the horizon see the horizon colour.
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    intensity: f64,
+    zenith: (f64, f64, f64),
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yc: [f64; 5],
+    theta_s: f64,
+}
+
+impl PhysicalSky {
+    /// `sun_direction` points towards the sun (y is up) and must be above the
+    /// horizon; `turbidity` is valid roughly in 2 (very clear) to 10 (hazy).
+    pub fn new(sun_direction: Vec3, turbidity: f64, intensity: f64) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        let theta_s = f64::acos(sun_direction.y().clamp(0.0, 1.0));
+        let (t2, ts, ts2, ts3) = (t * t, theta_s, theta_s * theta_s, theta_s.powi(3));
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t2 * (0.00166 * ts3 - 0.00375 * ts2 + 0.00209 * ts)
+            + t * (-0.02903 * ts3 + 0.06377 * ts2 - 0.03202 * ts + 0.00394)
+            + (0.11693 * ts3 - 0.21196 * ts2 + 0.06052 * ts + 0.25886);
+        let zenith_yc = t2 * (0.00275 * ts3 - 0.00610 * ts2 + 0.00317 * ts)
+            + t * (-0.04214 * ts3 + 0.08970 * ts2 - 0.04153 * ts + 0.00516)
+            + (0.15346 * ts3 - 0.26756 * ts2 + 0.06670 * ts + 0.26688);
+
+        Self {
+            sun_direction,
+            intensity,
+            zenith: (zenith_y, zenith_x, zenith_yc),
+            perez_y: [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            perez_x: [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            perez_yc: [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+            theta_s,
+        }
+    }
+
+    fn perez(c: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+        let [a, b, cc, d, e] = *c;
+        (1.0 + a * f64::exp(b / theta.cos()))
+            * (1.0 + cc * f64::exp(d * gamma) + e * gamma.cos().powi(2))
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let dir = direction.normalize();
+        // Keep theta just short of the horizon where the Perez function blows up.
+        let cos_theta = dir.y().max(0.01);
+        let theta = cos_theta.acos();
+        let gamma = dir.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let relative =
+            |c: &[f64; 5]| Self::perez(c, theta, gamma) / Self::perez(c, 0.0, self.theta_s);
+        let (zenith_lum, zenith_x, zenith_y) = self.zenith;
+        let lum = self.intensity * zenith_lum / SKY_LUMINANCE_SCALE * relative(&self.perez_y);
+        let x = zenith_x * relative(&self.perez_x);
+        let y = zenith_y * relative(&self.perez_yc);
+
+        xyy_to_linear_srgb(x, y, lum)
+    }
+}
+
+fn xyy_to_linear_srgb(x: f64, y: f64, lum: f64) -> Color {
+    if y <= 0.0 {
+        return Color::BLACK;
+    }
+    let cx = x / y * lum;
+    let cz = (1.0 - x - y) / y * lum;
+    Vec3(
+        (3.2406 * cx - 1.5372 * lum - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * lum + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * lum + 1.0570 * cz).max(0.0),
+    )
+}
+
+/// Equirectangular (latitude/longitude) environment image. The top row of the
+/// image is straight up and, before rotation, the centre column looks down -z.
+///
+/// Directions are importance sampled in proportion to texel luminance, so
+/// small bright features such as the sun in an HDRI are found directly.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    distribution: Distribution2D,
+    /// Rotation about the up axis, in radians.
+    rotation: f64,
+    intensity: f64,
+}
+
+impl EnvironmentMap {
+    /// Loads any image format the `image` crate understands, including Radiance
+    /// `.hdr` and OpenEXR.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let (width, height, pixels) = load_linear_rgb(path)
+            .with_context(|| format!("failed to load environment map {}", path.display()))?;
+        Self::from_pixels(width, height, pixels)
+            .with_context(|| format!("invalid environment map {}", path.display()))
+    }
+
+    /// Fails if the map is empty or `pixels` doesn't hold `width * height`
+    /// texels.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            bail!("environment map is empty ({}x{})", width, height);
+        }
+        if pixels.len() != width * height {
+            bail!(
+                "environment map has {} pixels but its size is {}x{}",
+                pixels.len(),
+                width,
+                height
+            );
+        }
+
+        // Bilinear filtering spreads each texel into its neighbours, so weight
+        // texels by their brightest neighbour to keep the density non-zero
+        // wherever there is radiance. Also weight by sin(theta) since rows
+        // near the poles cover less solid angle.
+        let luminance = |x: usize, y: usize| pixels[y * width + x].luminance().max(0.0);
+        let mut weights = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = f64::sin((y as f64 + 0.5) / height as f64 * PI);
+            for x in 0..width {
+                let mut max = 0.0f64;
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for dx in [width - 1, 0, 1] {
+                        max = max.max(luminance((x + dx) % width, ny));
+                    }
+                }
+                weights.push(max * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&weights, width);
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            distribution,
+            rotation: 0.0,
+            intensity: 1.0,
+        })
+    }
+
+    /// Turns the map about the up axis by `degrees`.
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = radians(degrees);
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Bilinearly filtered lookup at texture coordinates in [0, 1]², wrapping
+    /// horizontally and clamping vertically.
+    pub fn lookup(&self, u: f64, v: f64) -> Color {
+        let fx = u * self.width as f64 - 0.5;
+        let fy = (v * self.height as f64 - 0.5).clamp(0.0, (self.height - 1) as f64);
+        let x0 = fx.floor();
+        let y0 = fy.floor();
+        let (tx, ty) = (fx - x0, fy - y0);
+        let wrap = |x: f64| (x as i64).rem_euclid(self.width as i64) as usize;
+        let (xa, xb) = (wrap(x0), wrap(x0 + 1.0));
+        let ya = y0 as usize;
+        let yb = (ya + 1).min(self.height - 1);
+
+        let top = Vec3::lerp(&self.texel(xa, ya), &self.texel(xb, ya), tx);
+        let bottom = Vec3::lerp(&self.texel(xa, yb), &self.texel(xb, yb), tx);
+        Vec3::lerp(&top, &bottom, ty)
+    }
+}
+
+/// Maps a direction to equirectangular texture coordinates.
+pub fn direction_to_uv(direction: &Vec3) -> (f64, f64) {
+    let d = direction.normalize();
+    let u = 0.5 + f64::atan2(d.x(), -d.z()) / (2.0 * PI);
+    let v = f64::acos(d.y().clamp(-1.0, 1.0)) / PI;
+    (u, v)
+}
+
+/// Inverse of `direction_to_uv`.
+pub fn uv_to_direction(u: f64, v: f64) -> Vec3 {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    Vec3(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// Rotates `v` about the y axis by `angle` radians.
+fn rotate_y(v: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3(cos * v.x() + sin * v.z(), v.y(), -sin * v.x() + cos * v.z())
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = direction_to_uv(&rotate_y(direction, -self.rotation));
+        self.lookup(u, v).mul_scalar(self.intensity)
+    }
+
+    fn sample_direction(&self, u0: f64, u1: f64) -> Option<Vec3> {
+        let ((u, v), pdf) = self.distribution.sample(u0, u1);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(rotate_y(&uv_to_direction(u, v), self.rotation))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = direction_to_uv(&rotate_y(direction, -self.rotation));
+        let sin_theta = f64::sin(v * PI);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // Change of variables from the unit square to the sphere.
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Scene file description of the environment (the `[background]` table).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Background {
+    Gradient {
+        horizon: Color,
+        zenith: Color,
+    },
+    Solid {
+        color: Color,
+    },
+    Sky {
+        sun_direction: Vec3,
+        turbidity: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// Equirectangular image, resolved relative to the scene file.
+    /// `rotation` turns it about the up axis, in degrees.
+    Map {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        let sky = GradientSky::default();
+        Self::Gradient {
+            horizon: sky.horizon,
+            zenith: sky.zenith,
+        }
+    }
+}
+
+impl Background {
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Arc<dyn Environment>> {
+        Ok(match self {
+            Background::Gradient { horizon, zenith } => Arc::new(GradientSky {
+                horizon: *horizon,
+                zenith: *zenith,
+            }),
+            Background::Solid { color } => Arc::new(SolidColor(*color)),
+            Background::Sky {
+                sun_direction,
+                turbidity,
+                intensity,
+            } => Arc::new(PhysicalSky::new(*sun_direction, *turbidity, *intensity)),
+            Background::Map {
+                path,
+                rotation,
+                intensity,
+            } => Arc::new(
+                EnvironmentMap::load(&base_dir.join(path))?
+                    .with_rotation(*rotation)
+                    .with_intensity(*intensity),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uv_round_trips_through_directions() {
+        for i in 0..16 {
+            for j in 1..16 {
+                let (u, v) = (i as f64 / 16.0 + 0.01, j as f64 / 16.0);
+                let (u2, v2) = direction_to_uv(&uv_to_direction(u, v));
+                assert!(
+                    (u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9,
+                    "({}, {}) came back as ({}, {})",
+                    u,
+                    v,
+                    u2,
+                    v2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_directions_have_the_map_pdf() {
+        let pixels = (0..32)
+            .map(|i| Vec3(i as f64, 1.0, (i % 5) as f64))
+            .collect();
+        let map = EnvironmentMap::from_pixels(8, 4, pixels)
+            .unwrap()
+            .with_rotation(30.0);
+        for i in 0..50 {
+            let (u0, u1) = ((i as f64 * 0.618).fract(), (i as f64 + 0.5) / 50.0);
+            let direction = map.sample_direction(u0, u1).unwrap();
+            let ((u, v), pdf) = map.distribution.sample(u0, u1);
+            let expected = pdf / (2.0 * PI * PI * f64::sin(v * PI));
+            let got = map.pdf(&direction);
+            assert!(
+                (got - expected).abs() < 1e-6 * expected,
+                "pdf at ({}, {}) is {}, expected {}",
+                u,
+                v,
+                got,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        let grey = |n| vec![Vec3(0.5, 0.5, 0.5); n];
+        let err = |result: anyhow::Result<EnvironmentMap>| match result {
+            Ok(_) => panic!("invalid environment map accepted"),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(
+            err(EnvironmentMap::from_pixels(4, 2, grey(7))),
+            "environment map has 7 pixels but its size is 4x2"
+        );
+        assert_eq!(
+            err(EnvironmentMap::from_pixels(0, 2, grey(0))),
+            "environment map is empty (0x2)"
+        );
+        assert!(EnvironmentMap::from_pixels(4, 2, grey(8)).is_ok());
+    }
+}
@@ -0,0 +1,345 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use image::{GrayImage, ImageBuffer, Rgba};
+
+use crate::{
+    math::{clamp, RectSize},
+    sampler::SamplerKind,
+    vec::{Color, Vec3},
+    world::Camera,
+};
+
+/// Accumulates linear radiance for every pixel of a render, so samples can
+/// be added pass by pass and the current estimate read back at any point.
+/// Pixels are stored in image order, top row first.
+#[derive(Debug, Clone)]
+pub struct Film {
+    size: RectSize,
+    pixels: Vec<FilmPixel>,
+    passes: u32,
+    origin: FilmOrigin,
+}
+
+/// The render a film's samples were drawn for. More samples only add up to
+/// the right image if they come from the same scene, seed and sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FilmOrigin {
+    /// Hash of the scene description, 0 if unknown.
+    pub scene: u64,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    /// Samples per pixel the sampler lays its points out for. Resumed
+    /// renders keep this even when they take more samples, since the
+    /// stratified and Halton samplers place their points by it.
+    pub samples_per_pixel: u32,
+}
+
+impl FilmOrigin {
+    /// A render through `camera` of the scene hashing to `scene`.
+    pub const fn new(camera: &Camera, scene: u64) -> Self {
+        Self {
+            scene,
+            seed: camera.seed(),
+            sampler: camera.sampler(),
+            samples_per_pixel: camera.samples_per_pixel(),
+        }
+    }
+}
+
+/// Running totals of one pixel's samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FilmPixel {
+    sum: [f32; 3],
+    // Variance estimates cancel large sums, so these keep full precision.
+    luminance_sum: f64,
+    luminance_sq_sum: f64,
+    samples: u32,
+}
+
+impl FilmPixel {
+    /// Luminance below which errors are measured against this instead, so
+    /// near-black pixels don't soak up an adaptive render's budget.
+    const MIN_LUMINANCE: f64 = 1e-3;
+
+    pub fn add(&mut self, color: Color) {
+        let luminance = color.luminance();
+        self.sum[0] += color.x() as f32;
+        self.sum[1] += color.y() as f32;
+        self.sum[2] += color.z() as f32;
+        self.luminance_sum += luminance;
+        self.luminance_sq_sum += luminance * luminance;
+        self.samples += 1;
+    }
+
+    pub const fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Average of the samples so far, black before the first.
+    pub fn mean(&self) -> Color {
+        let scale = 1.0 / self.samples.max(1) as f64;
+        Vec3(
+            self.sum[0] as f64 * scale,
+            self.sum[1] as f64 * scale,
+            self.sum[2] as f64 * scale,
+        )
+    }
+
+    /// Standard error of the mean luminance, relative to the mean itself.
+    pub fn relative_error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.samples as f64;
+        let mean = self.luminance_sum / n;
+        let variance = (self.luminance_sq_sum - mean * self.luminance_sum) / (n - 1.0);
+        (variance.max(0.0) / n).sqrt() / mean.max(Self::MIN_LUMINANCE)
+    }
+}
+
+impl Film {
+    /// Header of saved films, followed by the version.
+    const MAGIC: &'static [u8; 8] = b"RDMFILM\x01";
+    /// Bytes of the header: the magic, width, height, passes, samples per
+    /// pixel, seed, scene hash and sampler.
+    const HEADER_BYTES: usize = 8 + 4 * 4 + 8 * 2 + 4;
+    /// Bytes per saved pixel: three f32 sums, two f64 moments and a u32.
+    const PIXEL_BYTES: usize = 3 * 4 + 2 * 8 + 4;
+
+    pub fn new(size: RectSize, origin: FilmOrigin) -> Self {
+        Self {
+            size,
+            pixels: vec![FilmPixel::default(); size.width as usize * size.height as usize],
+            passes: 0,
+            origin,
+        }
+    }
+
+    pub const fn size(&self) -> RectSize {
+        self.size
+    }
+
+    pub const fn origin(&self) -> &FilmOrigin {
+        &self.origin
+    }
+
+    /// Passes rendered into this film so far.
+    pub const fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    pub(crate) fn count_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    pub fn pixels(&self) -> &[FilmPixel] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [FilmPixel] {
+        &mut self.pixels
+    }
+
+    /// Samples taken over the whole film.
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.samples as u64).sum()
+    }
+
+    /// The current estimate, gamma corrected (gamma 2) to 8 bits.
+    pub fn to_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let RectSize { width, height } = self.size;
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let color = self.pixels[(y * width + x) as usize].mean();
+            let channel = |c: f64| (255.0 * clamp(c.sqrt(), 0.0, 0.999)) as u8;
+            Rgba([
+                channel(color.x()),
+                channel(color.y()),
+                channel(color.z()),
+                255,
+            ])
+        })
+    }
+
+    /// Samples each pixel took as a greyscale image, white where a pixel
+    /// took the most.
+    pub fn sample_count_image(&self) -> GrayImage {
+        let RectSize { width, height } = self.size;
+        let most = self.pixels.iter().map(|p| p.samples).max().unwrap_or(0);
+        GrayImage::from_fn(width, height, |x, y| {
+            let n = self.pixels[(y * width + x) as usize].samples;
+            image::Luma([(n as f64 / most.max(1) as f64 * 255.0).round() as u8])
+        })
+    }
+
+    /// Writes the accumulated samples, so a later render can add to them.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let origin = &self.origin;
+        let sampler = SamplerKind::ALL
+            .iter()
+            .position(|&k| k == origin.sampler)
+            .expect("ALL lists every sampler") as u32;
+        let mut bytes =
+            Vec::with_capacity(Self::HEADER_BYTES + self.pixels.len() * Self::PIXEL_BYTES);
+        bytes.extend_from_slice(Self::MAGIC);
+        bytes.extend_from_slice(&self.size.width.to_le_bytes());
+        bytes.extend_from_slice(&self.size.height.to_le_bytes());
+        bytes.extend_from_slice(&self.passes.to_le_bytes());
+        bytes.extend_from_slice(&origin.samples_per_pixel.to_le_bytes());
+        bytes.extend_from_slice(&origin.seed.to_le_bytes());
+        bytes.extend_from_slice(&origin.scene.to_le_bytes());
+        bytes.extend_from_slice(&sampler.to_le_bytes());
+        for p in &self.pixels {
+            for c in p.sum {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+            bytes.extend_from_slice(&p.luminance_sum.to_le_bytes());
+            bytes.extend_from_slice(&p.luminance_sq_sum.to_le_bytes());
+            bytes.extend_from_slice(&p.samples.to_le_bytes());
+        }
+        fs::write(path, bytes).with_context(|| format!("failed to write film {}", path.display()))
+    }
+
+    /// Reads a film written by `save`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("failed to read film {}", path.display()))?;
+        let (header, body) = bytes.split_at(bytes.len().min(Self::HEADER_BYTES));
+        if header.len() < Self::HEADER_BYTES || &header[..8] != Self::MAGIC {
+            bail!("{} is not a raydium film", path.display());
+        }
+        let u32_at = |b: &[u8], i: usize| u32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+        let u64_at = |b: &[u8], i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+        let f64_at = |b: &[u8], i: usize| f64::from_bits(u64_at(b, i));
+        let size = RectSize {
+            width: u32_at(header, 8),
+            height: u32_at(header, 12),
+        };
+        let Some(sampler) = SamplerKind::ALL.get(u32_at(header, 40) as usize) else {
+            bail!("{} was rendered with an unknown sampler", path.display());
+        };
+        let origin = FilmOrigin {
+            scene: u64_at(header, 32),
+            seed: u64_at(header, 24),
+            sampler: *sampler,
+            samples_per_pixel: u32_at(header, 20),
+        };
+        let Some(len) = (size.width as usize)
+            .checked_mul(size.height as usize)
+            .and_then(|n| n.checked_mul(Self::PIXEL_BYTES))
+        else {
+            bail!("{} is too large", path.display());
+        };
+        if body.len() != len {
+            bail!("{} is truncated or corrupt", path.display());
+        }
+
+        let pixels = body
+            .chunks_exact(Self::PIXEL_BYTES)
+            .map(|b| FilmPixel {
+                sum: [0, 4, 8].map(|i| f32::from_bits(u32_at(b, i))),
+                luminance_sum: f64_at(b, 12),
+                luminance_sq_sum: f64_at(b, 20),
+                samples: u32_at(b, 28),
+            })
+            .collect();
+        Ok(Self {
+            size,
+            pixels,
+            passes: u32_at(header, 16),
+            origin,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A 3x2 film with a few samples in each pixel.
+    fn sample_film() -> Film {
+        let origin = FilmOrigin {
+            scene: 0xdead_beef,
+            seed: 17,
+            sampler: SamplerKind::Sobol,
+            samples_per_pixel: 4,
+        };
+        let mut film = Film::new(
+            RectSize {
+                width: 3,
+                height: 2,
+            },
+            origin,
+        );
+        for (i, pixel) in film.pixels_mut().iter_mut().enumerate() {
+            for j in 0..=i {
+                pixel.add(Vec3(0.1 * i as f64, 0.5, j as f64));
+            }
+        }
+        film.count_pass();
+        film.count_pass();
+        film
+    }
+
+    /// A path in the temp directory unique to this test process and `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raydium-film-{}-{}", std::process::id(), name))
+    }
+
+    fn load_err(name: &str, bytes: &[u8]) -> String {
+        let path = temp_path(name);
+        fs::write(&path, bytes).unwrap();
+        let result = Film::load(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Ok(_) => panic!("{} loaded", name),
+            Err(e) => e.to_string().replace(&path.display().to_string(), "<film>"),
+        }
+    }
+
+    #[test]
+    fn saved_film_loads_back_unchanged() {
+        let film = sample_film();
+        let path = temp_path("round-trip");
+        film.save(&path).unwrap();
+        let loaded = Film::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(
+            (loaded.size().width, loaded.size().height),
+            (film.size().width, film.size().height)
+        );
+        assert_eq!(loaded.origin(), film.origin());
+        assert_eq!(loaded.passes(), 2);
+        assert_eq!(loaded.pixels(), film.pixels());
+    }
+
+    #[test]
+    fn truncated_or_foreign_files_are_rejected() {
+        let path = temp_path("saved");
+        sample_film().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            load_err("short-body", &bytes[..bytes.len() - 1]),
+            "<film> is truncated or corrupt"
+        );
+        assert_eq!(
+            load_err("short-header", &bytes[..Film::HEADER_BYTES - 1]),
+            "<film> is not a raydium film"
+        );
+        let mut foreign = bytes.clone();
+        foreign[0] = b'P';
+        assert_eq!(load_err("magic", &foreign), "<film> is not a raydium film");
+        let mut version = bytes;
+        version[7] = 2;
+        assert_eq!(
+            load_err("version", &version),
+            "<film> is not a raydium film"
+        );
+    }
+}
@@ -1,9 +1,15 @@
+use std::{f64::consts::PI, ops::Neg, sync::Arc};
 
+use anyhow::bail;
 
-
-use std::{f32, rc::Rc, sync::Arc};
-
-use crate::{ray::{HitRecord, Ray, Hittable}, vec::Vec3, material::Material};
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    onb::Onb,
+    ray::{HitRecord, Hittable, Ray},
+    sampler::Sampler,
+    vec::Vec3,
+};
 
 #[derive(Clone)]
 pub struct Sphere {
@@ -14,7 +20,6 @@ pub struct Sphere {
 
 impl Sphere {
     pub fn new(material: Arc<dyn Material>, center: Vec3, radius: f64) -> Self {
-        let p = f32::consts::PI;
         Self {
             center,
             radius,
@@ -25,33 +30,588 @@ impl Sphere {
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.len_sq();
-        let half_b = Vec3::dot(&oc, &ray.direction);
-        let c = oc.len_sq() - self.radius * self.radius;
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
 
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return None;
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(sphere_bounds(self.center, self.radius))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self
+            .hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
         }
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return Vec3::new_rand_unit_vector(sampler);
+        };
+        let (r1, r2) = sampler.next_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = f64::sqrt(1.0 - z * z);
+        let local = Vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::from_w(&(self.center - *origin)).local(&local)
+    }
+}
+
+impl Sphere {
+    /// Cosine of the half angle of the cone the sphere subtends from
+    /// `origin`, or `None` if `origin` is inside it and it surrounds every
+    /// direction.
+    fn cos_theta_max(&self, origin: &Vec3) -> Option<f64> {
+        let dist_sq = (self.center - *origin).len_sq();
+        let radius_sq = self.radius * self.radius;
+        (dist_sq > radius_sq).then(|| f64::sqrt(1.0 - radius_sq / dist_sq))
+    }
+}
+
+/// Sphere whose center moves linearly from `center0` at `time0` to `center1`
+/// at `time1`, for motion blur. Outside that interval it keeps moving along
+/// the same line.
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        material: Arc<dyn Material>,
+        center0: Vec3,
+        center1: Vec3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        Vec3::lerp(&self.center0, &self.center1, t)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let center = self.center(ray.time);
+        hit_sphere(center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        Some(Aabb::surrounding(
+            &sphere_bounds(self.center(time0), self.radius),
+            &sphere_bounds(self.center(time1), self.radius),
+        ))
+    }
+}
+
+fn hit_sphere(
+    center: Vec3,
+    radius: f64,
+    material: &Arc<dyn Material>,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    let oc = ray.origin - center;
+    let a = ray.direction.len_sq();
+    let half_b = Vec3::dot(&oc, &ray.direction);
+    let c = oc.len_sq() - radius * radius;
 
-        let sqrtd = discriminant.sqrt();
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
+    // Find the nearest root that lies in the acceptable range.
+    let mut root = (-half_b - sqrtd) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
         if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
+            return None;
+        }
+    }
+
+    let t = root;
+    let point = ray.at(t);
+    let outward_normal = (point - center).div_scalar(radius);
+    let mut hitrec = HitRecord::new(point, outward_normal, t, material.clone());
+    let (u, v, dpdu, dpdv) = sphere_coords(center, radius, &point);
+    hitrec.set_uv(u, v, dpdu, dpdv);
+    hitrec.set_face_normal(ray, outward_normal);
+    Some(hitrec)
+}
+
+fn sphere_bounds(center: Vec3, radius: f64) -> Aabb {
+    // Radius may be negative for hollow dielectric shells.
+    let r = radius.abs();
+    let r = Vec3(r, r, r);
+    Aabb::new(center - r, center + r)
+}
+
+/// Latitude/longitude coordinates of a point on a sphere: `u` runs around
+/// the y axis starting from -x, `v` from the bottom (-y) to the top pole.
+/// Also returns the partial derivatives of the point with respect to both.
+fn sphere_coords(center: Vec3, radius: f64, point: &Vec3) -> (f64, f64, Vec3, Vec3) {
+    let r = radius.abs();
+    let d = *point - center;
+    let n = d.div_scalar(r);
+    let theta = f64::acos((-n.y()).clamp(-1.0, 1.0));
+    let phi = f64::atan2(-n.z(), n.x()) + PI;
+    let (u, v) = (phi / (2.0 * PI), theta / PI);
+
+    let dpdu = Vec3(d.z(), 0.0, -d.x()).mul_scalar(2.0 * PI);
+    let sin_theta = theta.sin();
+    let dpdv = if sin_theta > 1e-8 {
+        Vec3(
+            -d.x() * d.y() / (r * sin_theta),
+            r * sin_theta,
+            -d.y() * d.z() / (r * sin_theta),
+        )
+        .mul_scalar(PI)
+    } else {
+        // At the poles u is degenerate; any tangent frame will do.
+        Vec3(0.0, 0.0, 0.0)
+    };
+    (u, v, dpdu, dpdv)
+}
+
+/// Single triangle with optional per-vertex normals and texture coordinates.
+/// Vertices wound counter-clockwise face the outward normal.
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uvs: Option<[(f64, f64); 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(material: Arc<dyn Material>, v0: Vec3, v1: Vec3, v2: Vec3) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, bary) = intersect_triangle(&self.vertices, ray, t_min, t_max)?;
+        Some(triangle_hit_record(
+            ray,
+            t,
+            bary,
+            &self.vertices,
+            self.normals.as_ref(),
+            self.uvs.as_ref(),
+            self.material.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(triangle_bounds(&self.vertices))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let [p0, p1, p2] = self.vertices;
+        let n = Vec3::cross(&(p1 - p0), &(p2 - p0));
+        let area = n.len() / 2.0;
+        self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY)
+            .map_or(0.0, |hit| area_pdf(&hit, direction, &n.normalize(), area))
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Square-root warp of the unit square onto barycentric coordinates.
+        let (u0, u1) = sampler.next_2d();
+        let s = u0.sqrt();
+        let b0 = 1.0 - s;
+        let b1 = u1 * s;
+        let [p0, p1, p2] = self.vertices;
+        p0.mul_scalar(b0) + p1.mul_scalar(b1) + p2.mul_scalar(1.0 - b0 - b1) - *origin
+    }
+}
+
+pub(crate) fn triangle_bounds(p: &[Vec3; 3]) -> Aabb {
+    Aabb::new(p[0], p[0]).grow(&p[1]).grow(&p[2]).pad(1e-4)
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin & Wald 2013).
+/// Returns the hit distance and the barycentric weights of each vertex.
+pub(crate) fn intersect_triangle(
+    p: &[Vec3; 3],
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, [f64; 3])> {
+    let d = ray.direction;
+
+    // Permute so the largest direction component becomes z.
+    let (ax, ay, az) = (d.x().abs(), d.y().abs(), d.z().abs());
+    let kz = if ax > ay && ax > az {
+        0
+    } else if ay > az {
+        1
+    } else {
+        2
+    };
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+    let permute = |v: Vec3| Vec3(v[kx], v[ky], v[kz]);
+
+    let d = permute(d);
+    let sx = -d.x() / d.z();
+    let sy = -d.y() / d.z();
+    let sz = 1.0 / d.z();
+
+    // Translate to the ray origin and shear so the ray points down +z.
+    let shear = |v: Vec3| {
+        let v = permute(v - ray.origin);
+        Vec3(v.x() + sx * v.z(), v.y() + sy * v.z(), v.z() * sz)
+    };
+    let p0 = shear(p[0]);
+    let p1 = shear(p[1]);
+    let p2 = shear(p[2]);
+
+    let e0 = p1.x() * p2.y() - p1.y() * p2.x();
+    let e1 = p2.x() * p0.y() - p2.y() * p0.x();
+    let e2 = p0.x() * p1.y() - p0.y() * p1.x();
+
+    let det = e0 + e1 + e2;
+    if det == 0.0 {
+        return None;
+    }
+    // Flip the edge functions so the inside is positive whichever way the
+    // triangle faces. A ray exactly on an edge only hits the triangle that
+    // owns it, so neighbours sharing the edge don't both report the hit.
+    let sign = det.signum();
+    for (e, a, b) in [(e0, p1, p2), (e1, p2, p0), (e2, p0, p1)] {
+        let e = e * sign;
+        if e < 0.0 || (e == 0.0 && !owns_edge(&a, &b, sign)) {
+            return None;
+        }
+    }
+
+    let inv_det = 1.0 / det;
+    let t = (e0 * p0.z() + e1 * p1.z() + e2 * p2.z()) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some((t, [e0 * inv_det, e1 * inv_det, e2 * inv_det]))
+}
+
+/// Tie-break for rays exactly on the edge from `a` to `b` (in the sheared
+/// space of `intersect_triangle`): of two triangles wound the same way, the
+/// edge runs in opposite directions, and only one of them sees it pointing
+/// into the upper half plane.
+fn owns_edge(a: &Vec3, b: &Vec3, sign: f64) -> bool {
+    let (dx, dy) = ((b.x() - a.x()) * sign, (b.y() - a.y()) * sign);
+    dy > 0.0 || (dy == 0.0 && dx > 0.0)
+}
+
+pub(crate) fn triangle_hit_record(
+    ray: &Ray,
+    t: f64,
+    bary: [f64; 3],
+    p: &[Vec3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: Option<&[(f64, f64); 3]>,
+    material: Arc<dyn Material>,
+) -> HitRecord {
+    let [b0, b1, b2] = bary;
+    let point = p[0].mul_scalar(b0) + p[1].mul_scalar(b1) + p[2].mul_scalar(b2);
+
+    let geometric = Vec3::cross(&(p[1] - p[0]), &(p[2] - p[0])).normalize();
+    let outward_normal = match normals {
+        Some(n) => {
+            let shading =
+                (n[0].mul_scalar(b0) + n[1].mul_scalar(b1) + n[2].mul_scalar(b2)).normalize();
+            // Keep the interpolated normal on the same side as the geometry.
+            if shading.dot(&geometric) < 0.0 {
+                shading.neg()
+            } else {
+                shading
             }
         }
+        None => geometric,
+    };
 
-        let t = root;
+    // Without texture coordinates, u and v are the barycentric weights of
+    // the second and third vertex.
+    let default_uvs = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+    let uv = uvs.unwrap_or(&default_uvs);
+    let u = uv[0].0 * b0 + uv[1].0 * b1 + uv[2].0 * b2;
+    let v = uv[0].1 * b0 + uv[1].1 * b1 + uv[2].1 * b2;
+
+    // Solve dp02 = du02 * dpdu + dv02 * dpdv (and likewise for 12).
+    let (du02, dv02) = (uv[0].0 - uv[2].0, uv[0].1 - uv[2].1);
+    let (du12, dv12) = (uv[1].0 - uv[2].0, uv[1].1 - uv[2].1);
+    let (dp02, dp12) = (p[0] - p[2], p[1] - p[2]);
+    let det = du02 * dv12 - dv02 * du12;
+    let (dpdu, dpdv) = if det.abs() > 1e-12 {
+        (
+            (dp02.mul_scalar(dv12) - dp12.mul_scalar(dv02)).div_scalar(det),
+            (dp12.mul_scalar(du02) - dp02.mul_scalar(du12)).div_scalar(det),
+        )
+    } else {
+        // Degenerate UVs: make up a frame around the geometric normal.
+        let frame = Onb::from_w(&geometric);
+        (frame.u, frame.v)
+    };
+
+    let mut hitrec = HitRecord::new(point, outward_normal, t, material);
+    hitrec.set_uv(u, v, dpdu, dpdv);
+    hitrec.set_face_normal(ray, outward_normal);
+    hitrec
+}
+
+/// Parallelogram spanned by edges `u` and `v` from corner `q`.
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+}
+
+impl Quad {
+    /// Fails if `u` and `v` are parallel or either is zero, since the quad
+    /// would have no area and no normal.
+    pub fn new(material: Arc<dyn Material>, q: Vec3, u: Vec3, v: Vec3) -> anyhow::Result<Self> {
+        if !Self::spans_area(&u, &v) {
+            bail!("quad edges must span a non-zero area");
+        }
+        let n = Vec3::cross(&u, &v);
+        let normal = n.normalize();
+        Ok(Self {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: normal.dot(&q),
+            w: n.div_scalar(n.dot(&n)),
+        })
+    }
+
+    pub(crate) fn spans_area(u: &Vec3, v: &Vec3) -> bool {
+        let area = Vec3::cross(u, v).len();
+        area > 1e-12 && area.is_finite()
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction);
+        // Ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        // Express the hit point in the (u, v) frame of the quad.
         let point = ray.at(t);
-        let outward_normal = (point - self.center).div_scalar(self.radius);
-        let mut hitrec = HitRecord::new(point, outward_normal, t, self.material.clone());
-        hitrec.set_face_normal(ray, outward_normal);
+        let planar = point - self.q;
+        let alpha = self.w.dot(&Vec3::cross(&planar, &self.v));
+        let beta = self.w.dot(&Vec3::cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut hitrec = HitRecord::new(point, self.normal, t, self.material.clone());
+        hitrec.set_uv(alpha, beta, self.u, self.v);
+        hitrec.set_face_normal(ray, self.normal);
         Some(hitrec)
     }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let bounds = Aabb::new(self.q, self.q)
+            .grow(&(self.q + self.u))
+            .grow(&(self.q + self.v))
+            .grow(&(self.q + self.u + self.v));
+        Some(bounds.pad(1e-4))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let area = Vec3::cross(&self.u, &self.v).len();
+        self.hit(&Ray::new(*origin, *direction), 0.001, f64::INFINITY)
+            .map_or(0.0, |hit| area_pdf(&hit, direction, &self.normal, area))
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.next_2d();
+        let point = self.q + self.u.mul_scalar(a) + self.v.mul_scalar(b);
+        point - *origin
+    }
 }
 
+/// Converts the density of picking a point uniformly on a surface of `area`
+/// to the solid angle density of `direction`, which reaches it at `hit`.
+fn area_pdf(hit: &HitRecord, direction: &Vec3, normal: &Vec3, area: f64) -> f64 {
+    let dist_sq = hit.t * hit.t * direction.len_sq();
+    let cosine = (direction.dot(normal) / direction.len()).abs();
+    if cosine == 0.0 {
+        return 0.0;
+    }
+    dist_sq / (cosine * area)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn triangle(v0: Vec3, v1: Vec3, v2: Vec3) -> Triangle {
+        Triangle::new(Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))), v0, v1, v2)
+    }
+
+    fn hits(triangles: &[Triangle], ray: &Ray) -> usize {
+        triangles
+            .iter()
+            .filter(|t| t.hit(ray, 0.001, f64::INFINITY).is_some())
+            .count()
+    }
+
+    #[test]
+    fn rays_through_a_shared_edge_hit_one_triangle() {
+        // A unit square split along its diagonal, both halves wound the same way.
+        let square = [
+            triangle(
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 0.0, 0.0),
+                Vec3(1.0, 1.0, 0.0),
+            ),
+            triangle(
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(1.0, 1.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+            ),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let s = rng.gen_range(0.01..0.99);
+            let target = Vec3(s, s, 0.0);
+            // From either side, at any angle.
+            let origin = Vec3(
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                if rng.gen_bool(0.5) { 1.0 } else { -1.0 },
+            );
+            let ray = Ray::new(origin, target - origin);
+            assert_eq!(hits(&square, &ray), 1, "ray towards {:?}", target);
+        }
+    }
+
+    #[test]
+    fn a_ray_through_a_shared_vertex_hits_one_triangle() {
+        // A hexagonal fan around the origin.
+        let spoke = |i: i32| {
+            let angle = i as f64 * PI / 3.0;
+            Vec3(angle.cos(), angle.sin(), 0.0)
+        };
+        let fan: Vec<_> = (0..6)
+            .map(|i| triangle(Vec3(0.0, 0.0, 0.0), spoke(i), spoke(i + 1)))
+            .collect();
+        for origin in [Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0)] {
+            let ray = Ray::new(origin, origin.mul_scalar(-1.0));
+            assert_eq!(hits(&fan, &ray), 1);
+        }
+        // Also through the spokes' far ends, shared by two triangles each.
+        for i in 0..6 {
+            let target = spoke(i).mul_scalar(0.5);
+            let ray = Ray::new(target + Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0));
+            assert_eq!(hits(&fan, &ray), 1, "ray through spoke {}", i);
+        }
+    }
+
+    #[test]
+    fn barycentrics_and_uvs_interpolate_the_vertices() {
+        let p = [
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(2.0, 0.0, 0.0),
+            Vec3(0.0, 2.0, 0.0),
+        ];
+        let ray = Ray::new(Vec3(0.5, 0.5, 1.0), Vec3(0.0, 0.0, -1.0));
+        let (t, bary) = intersect_triangle(&p, &ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(t, 1.0);
+        assert_eq!(bary, [0.5, 0.25, 0.25]);
+
+        let uvs = [(0.2, 0.2), (1.0, 0.2), (0.2, 1.0)];
+        let hit = triangle(p[0], p[1], p[2])
+            .with_uvs(uvs)
+            .hit(&ray, 0.001, f64::INFINITY)
+            .unwrap();
+        assert_eq!(
+            (hit.point.x(), hit.point.y(), hit.point.z()),
+            (0.5, 0.5, 0.0)
+        );
+        assert!((hit.u - 0.4).abs() < 1e-12 && (hit.v - 0.4).abs() < 1e-12);
+
+        // Without UVs, u and v are the second and third barycentrics.
+        let hit = triangle(p[0], p[1], p[2])
+            .hit(&ray, 0.001, f64::INFINITY)
+            .unwrap();
+        assert_eq!((hit.u, hit.v), (0.25, 0.25));
+    }
+
+    #[test]
+    fn degenerate_quads_are_rejected() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        let q = Vec3(0.0, 0.0, 0.0);
+        let x = Vec3(1.0, 0.0, 0.0);
+        assert!(Quad::new(material.clone(), q, x, Vec3(0.0, 1.0, 0.0)).is_ok());
+        assert!(Quad::new(material.clone(), q, x, x.mul_scalar(-2.0)).is_err());
+        assert!(Quad::new(material.clone(), q, x, Vec3::zero()).is_err());
+        assert!(Quad::new(material, q, x, Vec3(0.0, f64::NAN, 0.0)).is_err());
+    }
+}
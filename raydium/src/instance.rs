@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    onb::Onb,
+    ray::{DynHittable, HitRecord, Hittable, Ray},
+    sampler::Sampler,
+    transform::Transform,
+    vec::Vec3,
+};
+
+/// Places a shared object in the world under an affine transform, so one
+/// mesh (with its own BVH) can appear many times without copying geometry.
+/// A `Bvh` of instances forms a two-level acceleration structure.
+pub struct Instance<T: Hittable + Send + Sync + ?Sized = DynHittable> {
+    object: Arc<T>,
+    transform: Transform,
+}
+
+impl<T> Instance<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    pub fn new(object: Arc<T>, transform: Transform) -> Self {
+        Self { object, transform }
+    }
+
+    pub fn object(&self) -> &Arc<T> {
+        &self.object
+    }
+
+    pub const fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl<T> Hittable for Instance<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(&*self.object, &self.transform, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let bounds = self.object.bounding_box(time0, time1)?;
+        Some(self.transform.bounds(&bounds))
+    }
+
+    /// The object's density for the same direction in its own space, times
+    /// the Jacobian of mapping unit directions from object to world space.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let to_object = self.transform.inverse();
+        let local = to_object.vector(&direction.normalize());
+        let stretch = local.len();
+        if stretch == 0.0 {
+            return 0.0;
+        }
+        let pdf = self
+            .object
+            .pdf_value(&to_object.point(origin), &local.div_scalar(stretch));
+        pdf * to_object.matrix().determinant3().abs() / (stretch * stretch * stretch)
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let local = self
+            .object
+            .random(&self.transform.inverse().point(origin), sampler);
+        self.transform.vector(&local)
+    }
+}
+
+/// Intersects `object` placed in the world by `transform`. The ray direction
+/// is transformed without normalizing, so hit distances are the same in both
+/// spaces.
+pub(crate) fn hit_transformed<T: Hittable + ?Sized>(
+    object: &T,
+    transform: &Transform,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    let to_object = transform.inverse();
+    let local = Ray::new_timed(
+        to_object.point(&ray.origin),
+        to_object.vector(&ray.direction),
+        ray.time,
+    );
+    let mut hit = object.hit(&local, t_min, t_max)?;
+
+    // The inverse transpose keeps the normal on the same side of the ray,
+    // so the front/back face classification carries over.
+    hit.point = transform.point(&hit.point);
+    hit.normal = transform.normal(&hit.normal).normalize();
+    hit.dpdu = transform.vector(&hit.dpdu);
+    hit.dpdv = transform.vector(&hit.dpdv);
+    hit.shading = Onb::from_wu(&hit.normal, &hit.dpdu);
+    Some(hit)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{
+        geom::{Quad, Sphere},
+        material::Lambertian,
+        ray::NormalFace,
+        sampler::IndependentSampler,
+        transform::Quat,
+    };
+
+    #[test]
+    fn instanced_sphere_hits_where_the_transformed_sphere_is() {
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        let unit = Arc::new(Sphere::new(material.clone(), Vec3::zero(), 1.0));
+        let transform = Transform::from_trs(
+            &Vec3(3.0, 1.0, -2.0),
+            &Quat::from_euler_degrees(&Vec3(10.0, 70.0, 0.0)),
+            &Vec3(2.0, 2.0, 2.0),
+        )
+        .unwrap();
+        let instance = Instance::new(unit, transform);
+        let world = Sphere::new(material, Vec3(3.0, 1.0, -2.0), 2.0);
+
+        for (origin, direction) in [
+            (Vec3(3.0, 1.0, 10.0), Vec3(0.0, 0.0, -1.0)),
+            (Vec3(-5.0, 0.0, 0.0), Vec3(8.0, 1.5, -2.5)),
+            (Vec3(3.0, 1.0, -2.0), Vec3(0.3, -1.0, 0.2)),
+        ] {
+            let ray = Ray::new(origin, direction);
+            let a = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            let b = world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!((a.t - b.t).abs() < 1e-9, "t {} != {}", a.t, b.t);
+            assert!((a.point - b.point).len() < 1e-9);
+            assert!((a.normal - b.normal).len() < 1e-9);
+            let front = |h: &HitRecord| matches!(h.normal_face, NormalFace::FrontOuter);
+            assert_eq!(front(&a), front(&b));
+        }
+        let miss = Ray::new(Vec3(0.0, 5.0, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(instance.hit(&miss, 0.001, f64::INFINITY).is_none());
+
+        let bounds = instance.bounding_box(0.0, 0.0).unwrap();
+        assert!(bounds.contains(&Vec3(4.9, 1.0, -2.0)));
+        assert!(bounds.contains(&Vec3(3.0, -0.9, -2.0)));
+    }
+
+    #[test]
+    fn instanced_sphere_pdf_matches_the_transformed_sphere() {
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        let unit = Arc::new(Sphere::new(material.clone(), Vec3::zero(), 1.0));
+        let transform = Transform::from_trs(
+            &Vec3(3.0, 1.0, -2.0),
+            &Quat::from_euler_degrees(&Vec3(10.0, 70.0, 0.0)),
+            &Vec3(2.0, 2.0, 2.0),
+        )
+        .unwrap();
+        let instance = Instance::new(unit, transform);
+        let world = Sphere::new(material, Vec3(3.0, 1.0, -2.0), 2.0);
+
+        let origin = Vec3(-4.0, 2.0, 5.0);
+        let mut sampler = IndependentSampler::new(1);
+        for _ in 0..100 {
+            let direction = world.random(&origin, &mut sampler);
+            let (a, b) = (
+                instance.pdf_value(&origin, &direction),
+                world.pdf_value(&origin, &direction),
+            );
+            assert!(b > 0.0);
+            assert!((a - b).abs() < 1e-9 * b, "pdf {a} != {b}");
+
+            let sampled = instance.random(&origin, &mut sampler);
+            assert!(world
+                .hit(&Ray::new(origin, sampled), 0.001, f64::INFINITY)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn stretched_quad_pdf_integrates_to_one_and_random_hits_it() {
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        let quad = Quad::new(
+            material,
+            Vec3(-0.5, -0.5, 0.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        );
+        let transform = Transform::from_trs(
+            &Vec3(0.0, 0.0, -2.0),
+            &Quat::from_euler_degrees(&Vec3(30.0, 0.0, 20.0)),
+            &Vec3(3.0, 0.5, 1.0),
+        )
+        .unwrap();
+        let instance = Instance::new(Arc::new(quad.unwrap()), transform);
+        let origin = Vec3(0.2, 0.1, -1.4);
+        let mut sampler = IndependentSampler::new(2);
+
+        // Midpoint rule over a grid of equal-area cells in (z, phi).
+        let n = 300;
+        let mut total = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                let r = f64::sqrt(1.0 - z * z);
+                total += instance.pdf_value(&origin, &Vec3(r * phi.cos(), r * phi.sin(), z));
+            }
+        }
+        let integral = total * 4.0 * PI / (n * n) as f64;
+        assert!((integral - 1.0).abs() < 0.02, "integral {integral}");
+
+        for _ in 0..100 {
+            let direction = instance.random(&origin, &mut sampler);
+            let ray = Ray::new(origin, direction);
+            assert!(instance.hit(&ray, 0.001, f64::INFINITY).is_some());
+            assert!(instance.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use std::{fmt::Debug, str::FromStr, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ray::{HitRecord, Hittable, Ray},
+    sampler::Sampler,
+    vec::{Color, Vec3},
+    world::World,
+};
+
+/// Light transport: how much radiance arrives along a camera ray.
+pub trait Integrator: Debug + Send + Sync {
+    /// Radiance reaching the origin of `ray`, following paths no longer than
+    /// `depth` allows.
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        depth: PathDepth,
+        sampler: &mut dyn Sampler,
+    ) -> Color;
+}
+
+/// How long paths may get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathDepth {
+    /// Bounces after which a path is cut off regardless of what it carries.
+    pub max: u32,
+    /// Bounces every path takes before Russian roulette may end it.
+    pub roulette: u32,
+}
+
+impl PathDepth {
+    /// Russian roulette on the `bounces`th bounce: randomly ends paths that
+    /// carry little light, returning `None`, and scales the `throughput` of
+    /// the survivors up to make up for the ones lost. Unbiased, unlike
+    /// stopping at `max`.
+    fn roulette(
+        &self,
+        bounces: u32,
+        throughput: Color,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Color> {
+        if bounces <= self.roulette {
+            return Some(throughput);
+        }
+        let survival = throughput.max_component().min(0.95);
+        (sampler.next_1d() < survival).then(|| throughput.div_scalar(survival))
+    }
+}
+
+/// Path tracer that only follows the bounces materials sample. Lights are
+/// found by chance, so small ones converge slowly; mostly useful as a
+/// reference for the others.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NaivePathTracer;
+
+impl Integrator for NaivePathTracer {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        depth: PathDepth,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut radiance = Color::BLACK;
+        let mut throughput = Color::WHITE;
+        let mut ray = *ray;
+        for bounces in 0..depth.max {
+            let Some(hit) = world.trace(&ray, 0.001, f64::INFINITY) else {
+                return radiance + throughput * world.environment.radiance(&ray.direction);
+            };
+            radiance = radiance + throughput * hit.material.emitted(hit.u, hit.v, &hit.point);
+            let Some(sr) = hit.material.scatter(&ray, &hit, sampler) else {
+                break;
+            };
+            let Some(next) = depth.roulette(bounces + 1, throughput * sr.attenuation, sampler)
+            else {
+                break;
+            };
+            throughput = next;
+            ray = sr.scattered;
+        }
+        radiance
+    }
+}
+
+/// Path tracer with next-event estimation: every bounce off a material that
+/// can be evaluated also samples a light and the environment directly, and
+/// both strategies are combined by multiple importance sampling.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        depth: PathDepth,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut radiance = Color::BLACK;
+        let mut throughput = Color::WHITE;
+        let mut ray = *ray;
+        // Density with which the previous bounce picked `ray`, so emission it
+        // finds can be weighted against light sampling having found it too;
+        // `None` for camera rays and mirror-like bounces, which light
+        // sampling can't reproduce.
+        let mut bsdf_pdf: Option<f64> = None;
+
+        for bounces in 0..depth.max {
+            let Some(hit) = world.trace(&ray, 0.001, f64::INFINITY) else {
+                let env = world.environment.radiance(&ray.direction);
+                let weight = bsdf_pdf.map_or(1.0, |pdf| {
+                    power_heuristic(pdf, world.environment.pdf(&ray.direction))
+                });
+                return radiance + throughput * env.mul_scalar(weight);
+            };
+
+            let mut emitted = hit.material.emitted(hit.u, hit.v, &hit.point);
+            if let Some(pdf) = bsdf_pdf {
+                if emitted != Color::BLACK {
+                    let light_pdf = world.lights.pdf_value(&ray.origin, &ray.direction);
+                    emitted = emitted.mul_scalar(power_heuristic(pdf, light_pdf));
+                }
+            }
+            radiance = radiance + throughput * emitted;
+
+            let Some(sr) = hit.material.scatter(&ray, &hit, sampler) else {
+                break;
+            };
+            let pdf = hit.material.pdf(&ray, &hit, &sr.scattered);
+            bsdf_pdf = (pdf > 0.0).then_some(pdf);
+            if bsdf_pdf.is_some() {
+                let direct = sample_lights(&ray, world, &hit, sampler)
+                    + sample_environment(&ray, world, &hit, sampler);
+                radiance = radiance + throughput * direct;
+            }
+
+            let Some(next) = depth.roulette(bounces + 1, throughput * sr.attenuation, sampler)
+            else {
+                break;
+            };
+            throughput = next;
+            ray = sr.scattered;
+        }
+        radiance
+    }
+}
+
+/// Whitted-style ray tracer: mirrors and glass are followed, but everything
+/// else only gathers light arriving directly from each emitter and from the
+/// environment, with no diffuse interreflection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        depth: PathDepth,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut radiance = Color::BLACK;
+        let mut throughput = Color::WHITE;
+        let mut ray = *ray;
+        for bounces in 0..depth.max {
+            let Some(hit) = world.trace(&ray, 0.001, f64::INFINITY) else {
+                return radiance + throughput * world.environment.radiance(&ray.direction);
+            };
+            radiance = radiance + throughput * hit.material.emitted(hit.u, hit.v, &hit.point);
+            let Some(sr) = hit.material.scatter(&ray, &hit, sampler) else {
+                break;
+            };
+            if hit.material.pdf(&ray, &hit, &sr.scattered) <= 0.0 {
+                let Some(next) = depth.roulette(bounces + 1, throughput * sr.attenuation, sampler)
+                else {
+                    break;
+                };
+                throughput = next;
+                ray = sr.scattered;
+                continue;
+            }
+
+            let mut direct = Color::BLACK;
+            for light in world.lights.0.iter() {
+                if let Some((contribution, pdf, _)) =
+                    light_sample(&ray, world, &hit, light.as_ref(), sampler)
+                {
+                    direct = direct + contribution.div_scalar(pdf);
+                }
+            }
+            // The environment is seen along the bounce the material picked,
+            // but only if nothing blocks it.
+            if world.trace(&sr.scattered, 0.001, f64::INFINITY).is_none() {
+                direct =
+                    direct + sr.attenuation * world.environment.radiance(&sr.scattered.direction);
+            }
+            return radiance + throughput * direct;
+        }
+        radiance
+    }
+}
+
+/// White where a cosine-distributed ray from the first hit escapes within
+/// `distance`, black where it is blocked. Camera rays that miss are white.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            distance: f64::INFINITY,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        _depth: PathDepth,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let Some(hit) = world.trace(ray, 0.001, f64::INFINITY) else {
+            return Color::WHITE;
+        };
+        let direction = hit.shading.local(&Vec3::new_rand_cosine_direction(sampler));
+        let probe = Ray::new_timed(hit.point, direction, ray.time);
+        match world.trace(&probe, 0.001, self.distance) {
+            Some(_) => Color::BLACK,
+            None => Color::WHITE,
+        }
+    }
+}
+
+/// What `DebugView` shows of the first surface each camera ray hits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugChannel {
+    /// The surface normal facing the camera, mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// The material's albedo.
+    Albedo,
+    /// Distance from the camera, white up close fading to black at `far`.
+    Depth { far: f64 },
+}
+
+/// Shows one property of the first surface hit instead of lighting.
+/// Misses are black.
+#[derive(Debug, Clone, Copy)]
+pub struct DebugView {
+    pub channel: DebugChannel,
+}
+
+impl DebugView {
+    pub fn new(channel: DebugChannel) -> Self {
+        Self { channel }
+    }
+}
+
+impl Integrator for DebugView {
+    fn radiance(
+        &self,
+        ray: &Ray,
+        world: &World,
+        _depth: PathDepth,
+        _sampler: &mut dyn Sampler,
+    ) -> Color {
+        let Some(hit) = world.trace(ray, 0.001, f64::INFINITY) else {
+            return Color::BLACK;
+        };
+        match self.channel {
+            DebugChannel::Normals => (hit.normal + Color::WHITE).mul_scalar(0.5),
+            DebugChannel::Albedo => hit.material.albedo(&hit),
+            DebugChannel::Depth { far } => {
+                let distance = hit.t * ray.direction.len();
+                Color::WHITE.mul_scalar((1.0 - distance / far).max(0.0))
+            }
+        }
+    }
+}
+
+/// Scene file description of the integrator (`[render.integrator]`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IntegratorDesc {
+    /// `PathTracer`, the default.
+    #[default]
+    Path,
+    NaivePath,
+    Whitted,
+    AmbientOcclusion {
+        /// Occluders further than this are ignored; unbounded if unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        distance: Option<f64>,
+    },
+    Normals,
+    Albedo,
+    Depth {
+        #[serde(default = "default_far")]
+        far: f64,
+    },
+}
+
+fn default_far() -> f64 {
+    10.0
+}
+
+impl IntegratorDesc {
+    /// One of each kind, with default parameters.
+    pub const ALL: [IntegratorDesc; 7] = [
+        IntegratorDesc::Path,
+        IntegratorDesc::NaivePath,
+        IntegratorDesc::Whitted,
+        IntegratorDesc::AmbientOcclusion { distance: None },
+        IntegratorDesc::Normals,
+        IntegratorDesc::Albedo,
+        IntegratorDesc::Depth { far: 10.0 },
+    ];
+
+    /// The `type` this is written as in scene files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegratorDesc::Path => "path",
+            IntegratorDesc::NaivePath => "naive_path",
+            IntegratorDesc::Whitted => "whitted",
+            IntegratorDesc::AmbientOcclusion { .. } => "ambient_occlusion",
+            IntegratorDesc::Normals => "normals",
+            IntegratorDesc::Albedo => "albedo",
+            IntegratorDesc::Depth { .. } => "depth",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            IntegratorDesc::AmbientOcclusion {
+                distance: Some(distance),
+            } if distance.is_nan() || distance <= 0.0 => {
+                Err("ambient occlusion distance must be positive".into())
+            }
+            IntegratorDesc::Depth { far } if !(far.is_finite() && far > 0.0) => {
+                Err("depth far must be positive".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn build(&self) -> Arc<dyn Integrator> {
+        match *self {
+            IntegratorDesc::Path => Arc::new(PathTracer),
+            IntegratorDesc::NaivePath => Arc::new(NaivePathTracer),
+            IntegratorDesc::Whitted => Arc::new(Whitted),
+            IntegratorDesc::AmbientOcclusion { distance } => Arc::new(AmbientOcclusion {
+                distance: distance.unwrap_or(f64::INFINITY),
+            }),
+            IntegratorDesc::Normals => Arc::new(DebugView::new(DebugChannel::Normals)),
+            IntegratorDesc::Albedo => Arc::new(DebugView::new(DebugChannel::Albedo)),
+            IntegratorDesc::Depth { far } => Arc::new(DebugView::new(DebugChannel::Depth { far })),
+        }
+    }
+}
+
+/// Parses a bare `type` name, with default parameters.
+impl FromStr for IntegratorDesc {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|desc| desc.name() == s)
+            .ok_or_else(|| anyhow::anyhow!("unknown integrator `{}`", s))
+    }
+}
+
+/// Samples a point on `lights` as seen from `hit`. Returns the emitted
+/// radiance times the material's response, the density of the sampled
+/// direction and the shadow ray towards it, or `None` if nothing reaches.
+fn light_sample<L: Hittable + ?Sized>(
+    ray: &Ray,
+    world: &World,
+    hit: &HitRecord,
+    lights: &L,
+    sampler: &mut dyn Sampler,
+) -> Option<(Color, f64, Ray)> {
+    let direction = lights.random(&hit.point, sampler);
+    let pdf = lights.pdf_value(&hit.point, &direction);
+    if pdf <= 0.0 {
+        return None;
+    }
+    let shadow = Ray::new_timed(hit.point, direction, ray.time);
+    let f = hit.material.eval(ray, hit, &shadow);
+    if f == Color::BLACK {
+        return None;
+    }
+    let light = world.trace(&shadow, 0.001, f64::INFINITY)?;
+    let emitted = light.material.emitted(light.u, light.v, &light.point);
+    Some((f * emitted, pdf, shadow))
+}
+
+/// Light arriving at `hit` directly from a sampled point on an emitter,
+/// weighted against the material picking the same direction.
+fn sample_lights(ray: &Ray, world: &World, hit: &HitRecord, sampler: &mut dyn Sampler) -> Color {
+    if world.lights.is_empty() {
+        return Color::BLACK;
+    }
+    let Some((contribution, light_pdf, shadow)) =
+        light_sample(ray, world, hit, world.lights.as_ref(), sampler)
+    else {
+        return Color::BLACK;
+    };
+    let weight = power_heuristic(light_pdf, hit.material.pdf(ray, hit, &shadow));
+    contribution.mul_scalar(weight / light_pdf)
+}
+
+/// Light arriving at `hit` directly from a sampled direction of the
+/// environment, if it can be sampled and nothing is in the way.
+fn sample_environment(
+    ray: &Ray,
+    world: &World,
+    hit: &HitRecord,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let Some(direction) = world
+        .environment
+        .sample_direction(sampler.next_1d(), sampler.next_1d())
+    else {
+        return Color::BLACK;
+    };
+    let env_pdf = world.environment.pdf(&direction);
+    if env_pdf <= 0.0 {
+        return Color::BLACK;
+    }
+
+    let shadow = Ray::new_timed(hit.point, direction, ray.time);
+    let f = hit.material.eval(ray, hit, &shadow);
+    if f == Color::BLACK || world.trace(&shadow, 0.001, f64::INFINITY).is_some() {
+        return Color::BLACK;
+    }
+    let radiance = world.environment.radiance(&direction);
+    let weight = power_heuristic(env_pdf, hit.material.pdf(ray, hit, &shadow));
+    f * radiance.mul_scalar(weight / env_pdf)
+}
+
+/// Veach's power heuristic (beta = 2) MIS weight for a sample drawn with
+/// density `pdf` that `other_pdf` could also have drawn.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::SolidColor,
+        geom::Quad,
+        material::{Conductor, DiffuseLight, Lambertian, Material},
+        ray::{DynHittable, HitList},
+        sampler::IndependentSampler,
+    };
+
+    #[test]
+    fn power_heuristic_weights_of_both_strategies_sum_to_one() {
+        for (a, b) in [(1.0, 1.0), (0.3, 2.5), (10.0, 0.01), (1e-6, 4.0)] {
+            let sum = power_heuristic(a, b) + power_heuristic(b, a);
+            assert!((sum - 1.0).abs() < 1e-12, "{a}, {b}: {sum}");
+        }
+        assert_eq!(power_heuristic(2.0, 2.0), 0.5);
+        assert!(power_heuristic(3.0, 1.0) > 3.0 / 4.0);
+    }
+
+    #[test]
+    fn power_heuristic_gives_all_weight_to_the_only_strategy() {
+        assert_eq!(power_heuristic(0.7, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.7), 0.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+
+    /// A floor lit by a large overhead light and a uniform sky: next-event
+    /// estimation with MIS must converge to the same radiance as following
+    /// material samples alone.
+    #[test]
+    fn path_tracer_agrees_with_the_naive_one() {
+        let floors: [Arc<dyn Material>; 2] = [
+            Arc::new(Lambertian::new(Vec3(0.6, 0.6, 0.6))),
+            Arc::new(Conductor::default()),
+        ];
+        for floor in floors {
+            let light: Arc<DynHittable> = Arc::new(
+                Quad::new(
+                    Arc::new(DiffuseLight::new(Vec3(4.0, 4.0, 4.0))),
+                    Vec3(-2.0, 1.0, -2.0),
+                    Vec3(4.0, 0.0, 0.0),
+                    Vec3(0.0, 0.0, 4.0),
+                )
+                .unwrap(),
+            );
+            let floor: Arc<DynHittable> = Arc::new(
+                Quad::new(
+                    floor,
+                    Vec3(-5.0, 0.0, -5.0),
+                    Vec3(10.0, 0.0, 0.0),
+                    Vec3(0.0, 0.0, 10.0),
+                )
+                .unwrap(),
+            );
+            let mut objects = HitList::new();
+            objects.push(Arc::clone(&light));
+            objects.push(floor);
+            let mut lights = HitList::new();
+            lights.push(light);
+            let world = World::new(Arc::new(objects))
+                .with_lights(lights)
+                .with_environment(Arc::new(SolidColor(Vec3(0.5, 0.5, 0.5))));
+
+            let ray = Ray::new(Vec3(0.0, 0.5, 1.0), Vec3(0.0, -0.5, -1.0));
+            let depth = PathDepth {
+                max: 3,
+                roulette: 3,
+            };
+            let n = 20_000;
+            let mean = |integrator: &dyn Integrator| {
+                let mut sampler = IndependentSampler::new(3);
+                (0..n)
+                    .map(|_| integrator.radiance(&ray, &world, depth, &mut sampler).x())
+                    .sum::<f64>()
+                    / n as f64
+            };
+            let (naive, mis) = (mean(&NaivePathTracer), mean(&PathTracer));
+            assert!(
+                (naive - mis).abs() < 0.03 * naive,
+                "naive {naive} != mis {mis}"
+            );
+        }
+    }
+}
@@ -1,7 +1,25 @@
+pub mod aabb;
+pub mod bvh;
 pub mod render;
+pub mod scene;
 pub mod math;
 pub mod ray;
 pub mod geom;
+pub mod mesh;
+pub mod obj;
 pub mod world;
+pub mod environment;
+pub mod distribution;
+pub mod texture;
+pub mod perlin;
+pub mod onb;
+pub mod motion;
+pub mod transform;
+pub mod instance;
 pub mod vec;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod microfacet;
+pub mod principled;
+pub mod integrator;
+pub mod sampler;
+pub mod film;
\ No newline at end of file
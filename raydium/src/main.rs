@@ -1,17 +1,18 @@
 use eframe::epaint::ColorImage;
-use image::ImageBuffer;
-use rad::world::{Camera, CameraInfo};
+use rad::world::{Camera, CameraInfo, World};
 use rand::Rng;
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use image::Rgba;
-use log::*;
 use poll_promise::Promise;
-use rad::geom::Sphere;
-use rad::material::{Dielectric, Lambertian, Material, Metal};
+use rad::film::{Film, FilmOrigin};
+use rad::integrator::IntegratorDesc;
+use rad::material::Fresnel;
 use rad::math::RectSize;
-use rad::ray::HitList;
-use rad::render::RayRenderer;
+use rad::render::{AdaptiveSampling, CancelToken, RayRenderer, RenderProgress};
+use rad::sampler::SamplerKind;
+use rad::scene::{MaterialDesc, ObjectDesc, RenderSettings, Scene, SceneDesc};
 
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
 const IMAGE_WIDTH: u32 = 800;
@@ -25,6 +26,12 @@ use rad::vec::Vec3;
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
+    // An optional scene file replaces the built-in random scene.
+    let scene = match std::env::args().nth(1) {
+        Some(path) => Scene::load(Path::new(&path))?,
+        None => Raydium::random_scene().build(Path::new(""))?,
+    };
+
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(IMAGE_WIDTH as f32, IMAGE_HEIGHT as f32)),
         ..Default::default()
@@ -33,13 +40,7 @@ fn main() -> anyhow::Result<()> {
         "Raydium",
         options,
         Box::new(|cc| {
-            let app = Raydium::new(
-                cc,
-                RectSize {
-                    width: 1200,
-                    height: (1200. / (3. / 2.)) as u32,
-                },
-            );
+            let app = Raydium::new(cc, scene);
             Box::new(app)
         }),
     );
@@ -47,80 +48,91 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
 enum RenderState {
+    #[default]
     Ready,
     Running,
     Finished,
-    Progress(f32),
+    Cancelled,
+    Progress(RenderProgress),
     RequestDraw,
 }
 
-impl Default for RenderState {
-    fn default() -> Self {
-        Self::Ready
-    }
-}
-
-#[derive(Clone)]
-struct RaytraceFrame(ImageBuffer<Rgba<u8>, Vec<u8>>);
-
-unsafe impl Send for RaytraceFrame {}
-unsafe impl Sync for RaytraceFrame {}
-
 struct RayRendererAsync {
     this: RayRenderer,
-    world: Arc<HitList<Sphere>>,
+    world: World,
     surface_size: RectSize,
 }
 
 impl RayRendererAsync {
-    #[inline]
-    fn render_world_to_image(&self) -> RaytraceFrame {
-        RaytraceFrame(
-            self.this
-                .render_world_to_image(self.world.as_ref(), self.surface_size),
-        )
+    /// Renders until finished or `cancel`led, reporting to `progress` and
+    /// handing the image so far to `preview` after every pass, and returns
+    /// the last image.
+    fn render_progressively(
+        &self,
+        cancel: CancelToken,
+        progress: &(dyn Fn(&RenderProgress) + Sync),
+        preview: impl Fn(ColorImage),
+    ) -> ColorImage {
+        let renderer = self.this.clone().with_cancel_token(cancel);
+        let mut film = Film::new(self.surface_size, FilmOrigin::new(self.this.camera(), 0));
+        renderer.render_to_film_by_pass(&self.world, &mut film, progress, |film| {
+            preview(color_image(film))
+        });
+        color_image(&film)
     }
 }
 
+fn color_image(film: &Film) -> ColorImage {
+    let image = film.to_image();
+    let size = [image.width() as _, image.height() as _];
+    ColorImage::from_rgba_unmultiplied(size, image.as_flat_samples().as_slice())
+}
+
 struct Raydium {
     renderer: Arc<RayRendererAsync>,
+    camera: CameraInfo,
+    integrator: IntegratorDesc,
+    adaptive: Option<AdaptiveSampling>,
     render_state: RenderState,
     display_texture: Option<egui::TextureHandle>,
+    feed: Arc<Mutex<RenderFeed>>,
+    cancel: Option<CancelToken>,
     render_rx: Option<Promise<egui::TextureHandle>>,
 }
+
+/// What the render in flight has sent since the last frame.
+#[derive(Default)]
+struct RenderFeed {
+    preview: Option<ColorImage>,
+    progress: Option<RenderProgress>,
+}
 impl Raydium {
-    pub fn new(_cc: &eframe::CreationContext<'_>, surface_size: RectSize) -> Self {
+    pub fn new(_cc: &eframe::CreationContext<'_>, scene: Scene) -> Self {
         const BEGIN_STATE: RenderState = RenderState::Ready;
 
-        let world = Self::random_scene();
         let render_state = BEGIN_STATE;
-        let look_from = Vec3(13.0, 2.0, 3.0);
-        let look_at = Vec3::zero();
         let renderer = Arc::new(RayRendererAsync {
-            this: RayRenderer::new(Camera::with_info(&CameraInfo {
-                look_from,
-                look_at,
-                vert_up: Vec3(0., 1., 0.),
-                vert_fov: 20.,
-                aspect_ratio: 3.0 / 2.0,
-                aperture: 0.1,
-                focus_dist: 10., //(look_from - look_at).len(),
-                samples_per_pixel: 500,
-
-                ..Default::default()
-            })),
-            world: world.clone(),
-            surface_size,
+            this: RayRenderer::new(Camera::with_info(&scene.camera))
+                .with_integrator(scene.integrator.build())
+                .with_adaptive_sampling(scene.adaptive),
+            world: scene.world,
+            surface_size: scene.size,
         });
 
         //renderer.do_send(DrawWorld);
 
         Self {
             renderer,
+            camera: scene.camera,
+            integrator: scene.integrator,
+            adaptive: scene.adaptive,
             render_state,
             display_texture: None,
+            feed: Default::default(),
+            cancel: None,
             render_rx: None,
         }
     }
@@ -132,45 +144,128 @@ impl Raydium {
                     RenderState::Ready => "Ready".into(),
                     RenderState::Running => "Running".into(),
                     RenderState::Finished => "Finished".into(),
-                    RenderState::Progress(percent) => format!("{}%", percent * 100.0),
+                    RenderState::Cancelled => "Cancelled".into(),
+                    RenderState::Progress(p) => format!(
+                        "{:.0}% (pass {}, row {}/{}, {:.1}M rays, ETA {})",
+                        p.fraction() * 100.0,
+                        p.pass,
+                        p.rows_done,
+                        p.rows,
+                        p.rays as f64 / 1e6,
+                        p.eta
+                            .map_or_else(|| "-".into(), |eta| format!("{:.0?}", eta)),
+                    ),
                     RenderState::RequestDraw => "Requesting Draw".into(),
                 };
                 st
             };
 
             ui.label(format!("Render State: {}", state_text));
+
+            let mut integrator = self.integrator;
+            egui::ComboBox::from_label("Integrator")
+                .selected_text(integrator.name())
+                .show_ui(ui, |ui| {
+                    for desc in IntegratorDesc::ALL {
+                        // Keep the scene's parameters when re-picking its kind.
+                        let selected = desc.name() == integrator.name();
+                        if ui.selectable_label(selected, desc.name()).clicked() && !selected {
+                            integrator = desc;
+                        }
+                    }
+                });
+            let mut sampler = self.camera.sampler;
+            egui::ComboBox::from_label("Sampler")
+                .selected_text(sampler.name())
+                .show_ui(ui, |ui| {
+                    for kind in SamplerKind::ALL {
+                        ui.selectable_value(&mut sampler, kind, kind.name());
+                    }
+                });
+            if (integrator != self.integrator || sampler != self.camera.sampler)
+                && self.render_rx.is_none()
+            {
+                self.integrator = integrator;
+                self.camera.sampler = sampler;
+                self.rebuild_renderer();
+            }
             if ui.button("Render Frame").clicked() {
                 let rs = self.render_state;
-                if rs == RenderState::Ready || rs == RenderState::Finished {
+                if rs == RenderState::Ready
+                    || rs == RenderState::Finished
+                    || rs == RenderState::Cancelled
+                {
                     self.render_state = RenderState::Running;
                     let renderer = self.renderer.clone();
                     let ctx = ctx.clone();
+                    let feed = self.feed.clone();
+                    let cancel = CancelToken::new();
+                    self.cancel = Some(cancel.clone());
                     let receiver = Promise::spawn_thread("Raydium Render", move || {
-                        let image = renderer.render_world_to_image().0;
-                        let pixels = image.as_flat_samples();
-                        let size = [image.width() as _, image.height() as _];
-
-                        let texture = ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-                        ctx.load_texture("Raycast Image", texture, Default::default())
+                        let progress = |progress: &RenderProgress| {
+                            feed.lock().unwrap().progress = Some(*progress);
+                            ctx.request_repaint();
+                        };
+                        let image = renderer.render_progressively(cancel, &progress, |image| {
+                            feed.lock().unwrap().preview = Some(image);
+                            ctx.request_repaint();
+                        });
+                        ctx.load_texture("Raycast Image", image, Default::default())
                     });
                     self.render_rx = Some(receiver);
                 }
             }
+            let cancel = egui::Button::new("Cancel");
+            if ui.add_enabled(self.cancel.is_some(), cancel).clicked() {
+                if let Some(ref cancel) = self.cancel {
+                    cancel.cancel();
+                }
+            }
 
             ui.label(format!("{:?}", self.renderer.this.camera()));
             ui.label(format!("{:?}", self.renderer.this));
         });
     }
 
-    fn random_scene() -> Arc<HitList<Sphere>> {
-        let mut world = HitList::new();
+    /// Rebuilds the renderer after the integrator or sampler was changed.
+    fn rebuild_renderer(&mut self) {
+        self.renderer = Arc::new(RayRendererAsync {
+            this: RayRenderer::new(Camera::with_info(&self.camera))
+                .with_integrator(self.integrator.build())
+                .with_adaptive_sampling(self.adaptive),
+            world: self.renderer.world.clone(),
+            surface_size: self.renderer.surface_size,
+        });
+    }
+
+    fn random_scene() -> SceneDesc {
+        let mut materials = BTreeMap::new();
+        let mut objects = Vec::new();
+
+        materials.insert(
+            "ground".to_string(),
+            MaterialDesc::Lambertian {
+                albedo: Vec3(0.5, 0.5, 0.5).into(),
+            },
+        );
+        objects.push(ObjectDesc::Sphere {
+            center: Vec3(0., -1000., 0.),
+            radius: 1000.0,
+            material: "ground".into(),
+        });
 
-        let ground_mat = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
-        world.0.push(Arc::new(Sphere::new(
-            ground_mat,
-            Vec3(0., -1000., 0.),
-            1000.0,
-        )));
+        // Glass holds no per-sphere state, so every small glass sphere shares it.
+        materials.insert(
+            "glass".to_string(),
+            MaterialDesc::Dielectric {
+                ir: 1.5,
+                tint: Vec3::WHITE.into(),
+                absorption: Vec3::BLACK,
+                fresnel: Fresnel::Schlick,
+                roughness: 0.0,
+                anisotropy: 0.0,
+            },
+        );
 
         let mut rng = rand::thread_rng();
         for i in -11..11 {
@@ -183,81 +278,107 @@ impl Raydium {
                 );
 
                 if (center - Vec3(4., 0.2, 0.)).len() > 0.9 {
-                    let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
-                        let albedo = Vec3::new_rand() * Vec3::new_rand();
-                        Arc::new(Lambertian::new(albedo))
-                    } else if choose_mat < 0.95 {
-                        let albedo = Vec3::new_rand_range(0.5, 1.0);
-                        let fuzz = rng.gen_range(0.0..0.5);
-                        Arc::new(Metal::new(albedo, fuzz))
+                    let name = if choose_mat < 0.95 {
+                        let name = format!("sphere_{}_{}", i, j);
+                        let sphere_material = if choose_mat < 0.8 {
+                            let albedo = Vec3::new_rand(&mut rng) * Vec3::new_rand(&mut rng);
+                            MaterialDesc::Lambertian {
+                                albedo: albedo.into(),
+                            }
+                        } else {
+                            let albedo = Vec3::new_rand_range(&mut rng, 0.5, 1.0);
+                            let fuzz = rng.gen_range(0.0..0.5);
+                            MaterialDesc::Metal {
+                                albedo: albedo.into(),
+                                fuzz,
+                            }
+                        };
+                        materials.insert(name.clone(), sphere_material);
+                        name
                     } else {
-                        Arc::new(Dielectric::new(1.5))
+                        "glass".to_string()
                     };
-                    world
-                        .0
-                        .push(Arc::new(Sphere::new(sphere_material, center, 0.2)))
+                    objects.push(ObjectDesc::Sphere {
+                        center,
+                        radius: 0.2,
+                        material: name,
+                    });
                 }
             }
         }
-        let mat1 = Arc::new(Dielectric::new(1.5));
-        world
-            .0
-            .push(Arc::new(Sphere::new(mat1, Vec3(0., 1., 0.), 1.0)));
-        let mat2 = Arc::new(Lambertian::new(Vec3(0.4, 0.2, 0.1)));
-        world
-            .0
-            .push(Arc::new(Sphere::new(mat2, Vec3(-4., 1., 0.), 1.)));
-        let mat3 = Arc::new(Metal::new(Vec3(0.7, 0.6, 0.5), 0.0));
-        world
-            .0
-            .push(Arc::new(Sphere::new(mat3, Vec3(4., 1., 0.), 1.)));
-        Arc::new(world)
-    }
-    fn create_world() -> Arc<HitList<Sphere>> {
-        let mut world = HitList::new();
-
-        let mat_ground = Arc::new(Lambertian::new(Vec3(0.8, 0.8, 0.0)));
-        let mat_center = Arc::new(Lambertian::new(Vec3(0.1, 0.2, 0.5)));
-        let mat_left = Arc::new(Dielectric::new(1.5));
-        let mat_right = Arc::new(Metal::new(Vec3(0.8, 0.6, 0.2), 0.0));
-
-        world.0.push(Arc::new(Sphere::new(
-            mat_ground,
-            Vec3(0.0, -100.5, -1.0),
-            100.0,
-        )));
-        world.0.push(Arc::new(Sphere::new(
-            mat_center.clone(),
-            Vec3(0.0, 0.0, -1.0),
-            0.5,
-        )));
-        world.0.push(Arc::new(Sphere::new(
-            mat_left.clone(),
-            Vec3(-1.0, 0.0, -1.0),
-            0.5,
-        )));
-        world.0.push(Arc::new(Sphere::new(
-            mat_left.clone(),
-            Vec3(-1.0, 0.0, -1.0),
-            -0.4,
-        )));
-        world.0.push(Arc::new(Sphere::new(
-            mat_right.clone(),
-            Vec3(1.0, 0.0, -1.0),
-            0.5,
-        )));
-        Arc::new(world)
+
+        materials.insert(
+            "diffuse".to_string(),
+            MaterialDesc::Lambertian {
+                albedo: Vec3(0.4, 0.2, 0.1).into(),
+            },
+        );
+        materials.insert(
+            "mirror".to_string(),
+            MaterialDesc::Metal {
+                albedo: Vec3(0.7, 0.6, 0.5).into(),
+                fuzz: 0.0,
+            },
+        );
+        for (center, material) in [
+            (Vec3(0., 1., 0.), "glass"),
+            (Vec3(-4., 1., 0.), "diffuse"),
+            (Vec3(4., 1., 0.), "mirror"),
+        ] {
+            objects.push(ObjectDesc::Sphere {
+                center,
+                radius: 1.0,
+                material: material.into(),
+            });
+        }
+
+        SceneDesc {
+            camera: CameraInfo {
+                look_from: Vec3(13.0, 2.0, 3.0),
+                look_at: Vec3::zero(),
+                vert_up: Vec3(0., 1., 0.),
+                vert_fov: 20.,
+                aspect_ratio: 3.0 / 2.0,
+                aperture: 0.1,
+                focus_dist: 10.,
+                ..Default::default()
+            },
+            render: RenderSettings {
+                width: 1200,
+                height: (1200. / (3. / 2.)) as u32,
+                samples_per_pixel: 500,
+                ..Default::default()
+            },
+            materials,
+            objects,
+            ..Default::default()
+        }
     }
 }
 
 impl eframe::App for Raydium {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        {
+            let mut feed = self.feed.lock().unwrap();
+            if let Some(image) = feed.preview.take() {
+                self.display_texture =
+                    Some(ctx.load_texture("Raycast Image", image, Default::default()));
+            }
+            if let Some(progress) = feed.progress.take() {
+                self.render_state = RenderState::Progress(progress);
+            }
+        }
         if let Some(ref prom) = self.render_rx {
             if let Some(image) = prom.ready() {
                 self.display_texture = Some(image.clone());
                 // self.display_texture =
                 // Some(ctx.load_texture("Raycast Image", texture_image, Default::default()));
-                self.render_state = RenderState::Ready;
+                let cancelled = self.cancel.take().is_some_and(|c| c.is_cancelled());
+                self.render_state = if cancelled {
+                    RenderState::Cancelled
+                } else {
+                    RenderState::Finished
+                };
                 self.render_rx = None;
             }
         }
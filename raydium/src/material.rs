@@ -1,8 +1,13 @@
-use std::ops::Neg;
+use std::{f64::consts::PI, ops::Neg, sync::Arc};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    microfacet::{ComplexIor, DielectricInterface, Ggx},
     ray::{HitRecord, NormalFace, Ray},
-    vec::Vec3,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec::{Color, Vec3},
 };
 
 pub struct ScatterResult {
@@ -11,44 +16,91 @@ pub struct ScatterResult {
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult>;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult>;
+
+    /// Radiance scattered towards `-ray` per unit radiance arriving from
+    /// `scattered`: the BSDF times the cosine at the surface. Materials that
+    /// only scatter into isolated directions (mirrors, smooth glass) can't be
+    /// evaluated for arbitrary ones and return black.
+    fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Color;
+
+    /// Solid angle density with which `scatter` picks `scattered`. Zero for
+    /// the same materials `eval` is black for; those are never sampled
+    /// towards lights.
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64;
+
+    /// Light given off at a surface point. Black for anything that isn't a light.
+    fn emitted(&self, _u: f64, _v: f64, _point: &Vec3) -> Color {
+        Color::BLACK
+    }
+
+    /// Overall surface colour at `hit`, for debug views rather than shading.
+    fn albedo(&self, _hit: &HitRecord) -> Color {
+        Color::WHITE
+    }
 }
 unsafe impl Sync for Vec3 {}
 unsafe impl Sync for Lambertian {}
 
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
-    pub const fn new(albedo: Vec3) -> Self {
+    pub fn new(albedo: Vec3) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
-        let scatter_dir = {
-            let mut sd = hit.normal + Vec3::new_rand_unit_vector();
-            if sd.is_near_zero() {
-                sd = hit.normal
-            }
-            sd
-        };
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let scatter_dir = hit.shading.local(&Vec3::new_rand_cosine_direction(sampler));
         Some(ScatterResult {
-            scattered: Ray::new(hit.point, scatter_dir),
-            attenuation: self.albedo,
+            scattered: Ray::new_timed(hit.point, scatter_dir, ray.time),
+            attenuation: self.albedo.value(hit.u, hit.v, &hit.point),
         })
     }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Color {
+        let albedo = self.albedo.value(hit.u, hit.v, &hit.point);
+        albedo.mul_scalar(self.pdf(ray, hit, scattered))
+    }
+
+    fn pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = hit.normal.dot(&scattered.direction.normalize());
+        cosine.max(0.0) / PI
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.albedo.value(hit.u, hit.v, &hit.point)
+    }
 }
 
 pub struct Metal {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Self {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -58,73 +110,402 @@ impl Metal {
 
 impl Default for Metal {
     fn default() -> Self {
-        Self {
-            albedo: Vec3(1.0, 0.5, 1.0),
-            fuzz: 0.0,
-        }
+        Self::new(Vec3(1.0, 0.5, 1.0), 0.0)
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
         let reflected = reflect(ray.direction.normalize(), hit.normal);
-        let scattered = Ray::new(
+        let scattered = Ray::new_timed(
             hit.point,
-            reflected + Vec3::new_rand_unit_sphere().mul_scalar(self.fuzz),
+            reflected + Vec3::new_rand_unit_sphere(sampler).mul_scalar(self.fuzz),
+            ray.time,
         );
         if scattered.direction.dot(&hit.normal) > 0.0 {
             Some(ScatterResult {
                 scattered,
-                attenuation: self.albedo,
+                attenuation: self.albedo.value(hit.u, hit.v, &hit.point),
             })
         } else {
             None
         }
     }
+    // A fuzzed mirror picks from a cone with no closed-form density, so it
+    // is treated like a perfect mirror and never sampled towards lights.
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> Color {
+        Color::BLACK
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.albedo.value(hit.u, hit.v, &hit.point)
+    }
 }
 
+/// How `Dielectric` splits light between reflection and refraction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fresnel {
+    /// Schlick's approximation, as in the book.
+    #[default]
+    Schlick,
+    /// The unpolarized dielectric Fresnel equations.
+    Exact,
+}
+
+impl Fresnel {
+    /// Fraction of light reflected at incidence cosine `cos_theta`.
+    pub fn reflectance(self, cos_theta: f64, refraction_ratio: f64) -> f64 {
+        match self {
+            Fresnel::Schlick => reflectance(cos_theta, refraction_ratio),
+            Fresnel::Exact => fresnel_dielectric(cos_theta, refraction_ratio),
+        }
+    }
+}
+
+/// Glass, water and the like. Every hit either reflects or refracts, picked
+/// with the Fresnel reflectance as the probability. Refracted light is
+/// filtered by the `tint` texture, and light travelling inside is absorbed
+/// following Beer–Lambert with `absorption` coefficients per unit distance.
 pub struct Dielectric {
     ir: f64,
+    tint: Arc<dyn Texture>,
+    absorption: Color,
+    fresnel: Fresnel,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self {
+            ir,
+            tint: Arc::new(SolidColor::new(Color::WHITE)),
+            absorption: Color::BLACK,
+            fresnel: Fresnel::Schlick,
+        }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        self.with_tint_texture(Arc::new(SolidColor::new(tint)))
+    }
+
+    pub fn with_tint_texture(mut self, tint: Arc<dyn Texture>) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
     }
 }
 
 impl Default for Dielectric {
     fn default() -> Self {
-        Self { ir: 0.0 }
+        Self::new(1.5)
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterResult> {
-        let attenuation = Vec3::WHITE;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
         let refraction_ratio = match hit.normal_face {
             NormalFace::FrontOuter => 1.0 / self.ir,
             NormalFace::BackInner => self.ir,
         };
+        let mut attenuation = transmittance(&self.absorption, ray, hit);
         let unit_dir = ray.direction.normalize();
         let cos_theta = f64::min(Vec3::dot(&unit_dir.neg(), &hit.normal), 1.0);
         let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract {
+        let direction = if cannot_refract
+            || self.fresnel.reflectance(cos_theta, refraction_ratio) > sampler.next_1d()
+        {
             reflect(unit_dir, hit.normal)
         } else {
+            attenuation = attenuation * self.tint.value(hit.u, hit.v, &hit.point);
             refract(&unit_dir, &hit.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(hit.point, direction);
+        let scattered = Ray::new_timed(hit.point, direction, ray.time);
 
         Some(ScatterResult {
             attenuation,
             scattered,
         })
     }
+
+    // Smooth glass only scatters into the reflected and refracted directions.
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> Color {
+        Color::BLACK
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.tint.value(hit.u, hit.v, &hit.point)
+    }
+}
+
+/// Beer–Lambert transmittance of the path a ray took through a medium to
+/// reach `hit` from inside; white for hits from outside.
+fn transmittance(absorption: &Color, ray: &Ray, hit: &HitRecord) -> Color {
+    match hit.normal_face {
+        NormalFace::FrontOuter => Color::WHITE,
+        NormalFace::BackInner => {
+            let distance = hit.t * ray.direction.len();
+            Vec3(
+                f64::exp(-absorption.x() * distance),
+                f64::exp(-absorption.y() * distance),
+                f64::exp(-absorption.z() * distance),
+            )
+        }
+    }
+}
+
+/// Rough metal: a GGX microfacet BRDF with the Fresnel reflectance of a
+/// conductor's complex index of refraction.
+pub struct Conductor {
+    ior: ComplexIor,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(ior: ComplexIor, distribution: Ggx) -> Self {
+        Self { ior, distribution }
+    }
+
+    fn fresnel(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        self.ior.reflectance(wo.dot(&(*wo + *wi).normalize()))
+    }
+}
+
+impl Default for Conductor {
+    fn default() -> Self {
+        Self::new(ComplexIor::ALUMINIUM, Ggx::from_roughness(0.3, 0.0))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let wo = hit.shading.to_local(&ray.direction.normalize().neg());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = self
+            .distribution
+            .sample_reflection(&wo, sampler.next_1d(), sampler.next_1d())?;
+        // f cos / pdf: D and the Jacobian cancel, leaving F G / G1.
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some(ScatterResult {
+            scattered: Ray::new_timed(hit.point, hit.shading.local(&wi), ray.time),
+            attenuation: self.fresnel(&wo, &wi).mul_scalar(weight),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_directions(ray, hit, scattered);
+        let reflection = self.distribution.reflection(&wo, &wi);
+        if reflection == 0.0 {
+            return Color::BLACK;
+        }
+        self.fresnel(&wo, &wi).mul_scalar(reflection)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_directions(ray, hit, scattered);
+        self.distribution.reflection_pdf(&wo, &wi)
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color {
+        self.ior.reflectance(1.0)
+    }
+}
+
+/// Frosted glass: reflection and transmission through GGX microfacets, with
+/// the same tint and absorption as `Dielectric`.
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: Ggx,
+    tint: Arc<dyn Texture>,
+    absorption: Color,
+    fresnel: Fresnel,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, distribution: Ggx) -> Self {
+        Self {
+            ir,
+            distribution,
+            tint: Arc::new(SolidColor::new(Color::WHITE)),
+            absorption: Color::BLACK,
+            fresnel: Fresnel::Exact,
+        }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        self.with_tint_texture(Arc::new(SolidColor::new(tint)))
+    }
+
+    pub fn with_tint_texture(mut self, tint: Arc<dyn Texture>) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
+    }
+
+    fn interface(&self, hit: &HitRecord) -> DielectricInterface {
+        DielectricInterface {
+            distribution: self.distribution,
+            eta: match hit.normal_face {
+                NormalFace::FrontOuter => self.ir,
+                NormalFace::BackInner => 1.0 / self.ir,
+            },
+            fresnel: self.fresnel,
+        }
+    }
+
+    /// Filter on light arriving from local direction `wi`.
+    fn filter(&self, ray: &Ray, hit: &HitRecord, wi: &Vec3) -> Color {
+        let absorbed = transmittance(&self.absorption, ray, hit);
+        if wi.z() < 0.0 {
+            self.tint.value(hit.u, hit.v, &hit.point) * absorbed
+        } else {
+            absorbed
+        }
+    }
+}
+
+impl Default for RoughDielectric {
+    fn default() -> Self {
+        Self::new(1.5, Ggx::from_roughness(0.3, 0.0))
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let wo = hit.shading.to_local(&ray.direction.normalize().neg());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = self.interface(hit).sample(
+            &wo,
+            sampler.next_1d(),
+            sampler.next_1d(),
+            sampler.next_1d(),
+        )?;
+        // Picking reflection with probability F cancels F (or 1 - F) and the
+        // Jacobians out of the weight, leaving G / G1 on either branch.
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        Some(ScatterResult {
+            scattered: Ray::new_timed(hit.point, hit.shading.local(&wi), ray.time),
+            attenuation: self.filter(ray, hit, &wi).mul_scalar(weight),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Color {
+        let (wo, wi) = local_directions(ray, hit, scattered);
+        let value = self.interface(hit).eval(&wo, &wi);
+        self.filter(ray, hit, &wi).mul_scalar(value)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
+        let (wo, wi) = local_directions(ray, hit, scattered);
+        self.interface(hit).pdf(&wo, &wi)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.tint.value(hit.u, hit.v, &hit.point)
+    }
+}
+
+/// Outgoing (towards the viewer) and incoming directions in the shading frame.
+fn local_directions(ray: &Ray, hit: &HitRecord, scattered: &Ray) -> (Vec3, Vec3) {
+    (
+        hit.shading.to_local(&ray.direction.normalize().neg()),
+        hit.shading.to_local(&scattered.direction.normalize()),
+    )
+}
+
+/// Area light: emits `emit` from every point of the surface and scatters nothing.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        None
+    }
+
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> Color {
+        Color::BLACK
+    }
+
+    fn pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        self.emit.value(u, v, point)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.emit.value(hit.u, hit.v, &hit.point)
+    }
 }
 
 pub fn reflectance(cosine: f64, reflection_index: f64) -> f64 {
@@ -136,14 +517,83 @@ pub fn reflectance(cosine: f64, reflection_index: f64) -> f64 {
     r0 + (1.0 - r0) * f64::powf(1.0 - cosine, 5.0)
 }
 
+/// Exact Fresnel reflectance of unpolarized light hitting a dielectric
+/// boundary at incidence cosine `cos_i`, with `eta` the ratio of the
+/// incident to the transmitted index. One on total internal reflection.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_s * r_s + r_p * r_p) / 2.0
+}
+
 #[inline]
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - n.mul_scalar(2.0 * v.dot(&n))
 }
 
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = f64::min(Vec3::dot(&uv.neg(), &n), 1.0);
+    let cos_theta = f64::min(Vec3::dot(&uv.neg(), n), 1.0);
     let r_out_perp = (*uv + n.mul_scalar(cos_theta)).mul_scalar(etai_over_etat);
     let r_out_parallel = n.mul_scalar(-f64::sqrt(f64::abs(1.0 - r_out_perp.len_sq()))); //n.mul_scalar(-(1.0 - r_out_perp.len_sq()).abs().sqrt());
     r_out_perp + r_out_parallel
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_incidence_reflects_the_index_contrast() {
+        for n in [1.33, 1.5, 2.4] {
+            let expected = ((n - 1.0) / (n + 1.0)) * ((n - 1.0) / (n + 1.0));
+            for eta in [1.0 / n, n] {
+                assert!((fresnel_dielectric(1.0, eta) - expected).abs() < 1e-12);
+                assert!((reflectance(1.0, eta) - expected).abs() < 1e-12);
+            }
+        }
+        assert!(fresnel_dielectric(0.3, 1.0) < 1e-12);
+    }
+
+    #[test]
+    fn total_internal_reflection_above_the_critical_angle() {
+        let eta = 1.5;
+        let critical_cos = f64::sqrt(1.0 - 1.0 / (eta * eta));
+        for cos in [critical_cos - 1e-6, 0.5, 0.1, 0.0] {
+            assert_eq!(fresnel_dielectric(cos, eta), 1.0);
+        }
+        let below = fresnel_dielectric(critical_cos + 0.01, eta);
+        assert!(below > 0.04 && below < 1.0);
+        // Leaving the denser medium never totally reflects.
+        assert!(fresnel_dielectric(0.1, 1.0 / eta) < 1.0);
+    }
+
+    #[test]
+    fn schlick_stays_close_to_the_exact_reflectance() {
+        for eta in [1.0 / 1.5, 1.0 / 1.33] {
+            let mut previous = 0.0;
+            for cos in [1.0, 0.9, 0.7, 0.5, 0.3, 0.1, 0.01] {
+                let exact = fresnel_dielectric(cos, eta);
+                let schlick = reflectance(cos, eta);
+                // Schlick is known to drift most towards grazing angles.
+                let tolerance = if cos >= 0.5 { 0.02 } else { 0.07 };
+                assert!(
+                    (exact - schlick).abs() < tolerance,
+                    "at cos {} exact {} vs Schlick {}",
+                    cos,
+                    exact,
+                    schlick
+                );
+                // Both rise towards grazing incidence.
+                assert!(exact >= previous);
+                previous = exact;
+            }
+            assert!((fresnel_dielectric(0.0, eta) - 1.0).abs() < 1e-12);
+            assert!((reflectance(0.0, eta) - 1.0).abs() < 1e-12);
+        }
+    }
+}
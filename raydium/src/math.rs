@@ -1,15 +1,11 @@
-use rand::{rngs::ThreadRng, Rng, SeedableRng};
-
-use crate::vec::Vec3;
-
 pub type IOResult<T> = Result<T, Box<dyn std::error::Error>>;
 
-pub const INF: f64 = std::f64::INFINITY;
-pub const PI: f64 = 3.1415926535897932384626433832795;
-pub const HALF_PI: f64 = 1.5707963267948966192313216916398;
+pub const INF: f64 = f64::INFINITY;
+pub const PI: f64 = std::f64::consts::PI;
+pub const HALF_PI: f64 = std::f64::consts::FRAC_PI_2;
 pub const DEG_TO_RAD: f64 = 0.017453292519943295769236907684886;
 pub const RAD_TO_DEG: f64 = 57.295779513082320876798154814105;
-pub const EULER: f64 = 2.718281828459045235360287471352;
+pub const EULER: f64 = std::f64::consts::E;
 
 #[inline]
 pub fn radians(deg: f64) -> f64 {
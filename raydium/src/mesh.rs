@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use anyhow::bail;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    geom::{intersect_triangle, triangle_bounds, triangle_hit_record},
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    vec::Vec3,
+};
+
+/// Vertex and index buffers shared by every triangle of a mesh.
+///
+/// `normals` and `uvs` are either empty or hold one entry per position.
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl MeshData {
+    fn vertices(&self, face: usize) -> [Vec3; 3] {
+        let [a, b, c] = self.indices[face];
+        [
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        ]
+    }
+
+    fn normals(&self, face: usize) -> Option<[Vec3; 3]> {
+        if self.normals.is_empty() {
+            return None;
+        }
+        let [a, b, c] = self.indices[face];
+        Some([
+            self.normals[a as usize],
+            self.normals[b as usize],
+            self.normals[c as usize],
+        ])
+    }
+
+    fn uvs(&self, face: usize) -> Option<[(f64, f64); 3]> {
+        if self.uvs.is_empty() {
+            return None;
+        }
+        let [a, b, c] = self.indices[face];
+        Some([
+            self.uvs[a as usize],
+            self.uvs[b as usize],
+            self.uvs[c as usize],
+        ])
+    }
+}
+
+/// One face of a `TriangleMesh`, referencing the shared buffers by index.
+pub struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let p = self.mesh.vertices(self.face);
+        let (t, bary) = intersect_triangle(&p, ray, t_min, t_max)?;
+        Some(triangle_hit_record(
+            ray,
+            t,
+            bary,
+            &p,
+            self.mesh.normals(self.face).as_ref(),
+            self.mesh.uvs(self.face).as_ref(),
+            self.mesh.material.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(triangle_bounds(&self.mesh.vertices(self.face)))
+    }
+}
+
+/// Indexed triangle mesh with its own BVH over its faces, so it can be
+/// dropped into a world as a single object.
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: Bvh<MeshTriangle>,
+}
+
+impl TriangleMesh {
+    /// Fails if the normals or UVs don't match the positions one to one, or
+    /// an index is out of range.
+    pub fn new(data: MeshData) -> anyhow::Result<Self> {
+        let n = data.positions.len();
+        if !data.normals.is_empty() && data.normals.len() != n {
+            bail!(
+                "mesh has {} normals for {} positions",
+                data.normals.len(),
+                n
+            );
+        }
+        if !data.uvs.is_empty() && data.uvs.len() != n {
+            bail!("mesh has {} uvs for {} positions", data.uvs.len(), n);
+        }
+        if data.indices.iter().flatten().any(|&i| i as usize >= n) {
+            bail!("mesh index out of range of {} positions", n);
+        }
+
+        let data = Arc::new(data);
+        let faces = (0..data.indices.len())
+            .map(|face| {
+                Arc::new(MeshTriangle {
+                    mesh: data.clone(),
+                    face,
+                })
+            })
+            .collect();
+        let bvh = Bvh::new(faces, 0.0, 0.0);
+        Ok(Self { data, bvh })
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.data.indices.len()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.bvh.bounding_box(time0, time1)
+    }
+}
@@ -0,0 +1,541 @@
+use std::{f64::consts::PI, ops::Neg};
+
+use crate::{
+    material::{reflect, refract, Fresnel},
+    vec::{Color, Vec3},
+};
+
+/// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith
+/// shadowing. Directions are in a local shading frame with the macro surface
+/// normal along +z and `alpha_x` the roughness along the tangent (+x).
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    /// Below this the distribution is a near-perfect mirror, and smaller
+    /// values only lose precision.
+    const MIN_ALPHA: f64 = 1e-4;
+
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(Self::MIN_ALPHA),
+            alpha_y: alpha_y.max(Self::MIN_ALPHA),
+        }
+    }
+
+    /// Maps perceptual `roughness` in [0, 1] to alpha = roughness², and
+    /// `anisotropy` in [-1, 1] to stretching along the tangent (positive) or
+    /// the bitangent (negative), as in Disney's principled BRDF.
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness * roughness;
+        let aspect = f64::sqrt(1.0 - 0.9 * anisotropy.abs());
+        if anisotropy >= 0.0 {
+            Self::new(alpha / aspect, alpha * aspect)
+        } else {
+            Self::new(alpha * aspect, alpha / aspect)
+        }
+    }
+
+    /// Density of microfacet normal `wh` per unit projected area.
+    pub fn d(&self, wh: &Vec3) -> f64 {
+        let cos2 = wh.z() * wh.z();
+        if cos2 == 0.0 {
+            return 0.0;
+        }
+        let e = (wh.x() / self.alpha_x).powi(2) + (wh.y() / self.alpha_y).powi(2) + cos2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith's auxiliary function: the shadowed microfacet area per visible
+    /// area when looking along `w`.
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let t = (w.x() * self.alpha_x).powi(2) + (w.y() * self.alpha_y).powi(2);
+        (f64::sqrt(1.0 + t / cos2) - 1.0) / 2.0
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`
+    /// (height-correlated).
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal visible from `wo` (which must be above
+    /// the surface) for uniform `u0`, `u1` in [0, 1) (Heitz, "Sampling the
+    /// GGX Distribution of Visible Normals", 2018).
+    pub fn sample_wh(&self, wo: &Vec3, u0: f64, u1: f64) -> Vec3 {
+        // Stretch to the hemisphere configuration.
+        let vh = Vec3(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).normalize();
+        let len_sq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len_sq > 0.0 {
+            Vec3(-vh.y(), vh.x(), 0.0).div_scalar(len_sq.sqrt())
+        } else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&vh, &t1);
+
+        // Uniform point on the projected disk, warped towards the visible half.
+        let r = u0.sqrt();
+        let phi = 2.0 * PI * u1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * f64::sqrt(1.0 - p1 * p1) + s * r * phi.sin();
+        let p3 = f64::sqrt((1.0 - p1 * p1 - p2 * p2).max(0.0));
+        let nh = t1.mul_scalar(p1) + t2.mul_scalar(p2) + vh.mul_scalar(p3);
+
+        // Unstretch.
+        Vec3(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .normalize()
+    }
+
+    /// Density with which `sample_wh` picks `wh` when looking from `wo`.
+    /// Zero for normals below the surface, which are never sampled.
+    pub fn pdf(&self, wo: &Vec3, wh: &Vec3) -> f64 {
+        if wo.z() == 0.0 || wh.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(wh).max(0.0) * self.d(wh) / wo.z().abs()
+    }
+
+    /// Reflects `wo` about a sampled visible microfacet; `None` if the
+    /// reflection points below the surface.
+    pub fn sample_reflection(&self, wo: &Vec3, u0: f64, u1: f64) -> Option<Vec3> {
+        let wh = self.sample_wh(wo, u0, u1);
+        let wi = wh.mul_scalar(2.0 * wo.dot(&wh)) - *wo;
+        (wi.z() > 0.0).then_some(wi)
+    }
+
+    /// The reflection BRDF times the cosine of `wi`, without the Fresnel
+    /// term: D G / (4 cos_o).
+    pub fn reflection(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wh = (*wo + *wi).normalize();
+        self.d(&wh) * self.g(wo, wi) / (4.0 * wo.z())
+    }
+
+    /// Density with which `sample_reflection` picks `wi`.
+    pub fn reflection_pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wh = (*wo + *wi).normalize();
+        self.pdf(wo, &wh) / (4.0 * wo.dot(&wh))
+    }
+}
+
+/// Rough boundary between two dielectrics that reflects or refracts
+/// (Walter et al., "Microfacet Models for Refraction through Rough
+/// Surfaces", 2007). `eta` is the ratio of the index of refraction beyond
+/// the surface to the one on the viewer's side. Like the smooth `Dielectric`,
+/// radiance is not rescaled by eta² on crossing.
+#[derive(Debug, Clone, Copy)]
+pub struct DielectricInterface {
+    pub distribution: Ggx,
+    pub eta: f64,
+    pub fresnel: Fresnel,
+}
+
+impl DielectricInterface {
+    /// Reflects or refracts `wo` through a sampled visible microfacet, picking
+    /// reflection with the Fresnel probability. `None` if the result points
+    /// to the wrong side of the surface.
+    pub fn sample(&self, wo: &Vec3, u0: f64, u1: f64, u2: f64) -> Option<Vec3> {
+        let wh = self.distribution.sample_wh(wo, u0, u1);
+        let cos_o = wo.dot(&wh);
+        let f = self.fresnel.reflectance(cos_o, 1.0 / self.eta);
+        let sin2_t = (1.0 - cos_o * cos_o) / (self.eta * self.eta);
+        if sin2_t >= 1.0 || u2 < f {
+            let wi = reflect(wo.neg(), wh);
+            (wi.z() > 0.0).then_some(wi)
+        } else {
+            let wi = refract(&wo.neg(), &wh, 1.0 / self.eta);
+            (wi.z() < 0.0).then_some(wi)
+        }
+    }
+
+    /// The microfacet normal that turns `wo` into `wi`, facing `wo`, or
+    /// `None` if no microfacet can.
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+        let reflected = wi.z() > 0.0;
+        let wh = if reflected {
+            *wo + *wi
+        } else {
+            *wo + wi.mul_scalar(self.eta)
+        };
+        if wh.is_near_zero() {
+            return None;
+        }
+        let wh = wh.normalize();
+        let wh = if wh.z() < 0.0 { wh.neg() } else { wh };
+        let valid = wo.z() > 0.0 && wo.dot(&wh) > 0.0 && (wi.dot(&wh) > 0.0) == reflected;
+        valid.then_some(wh)
+    }
+
+    /// The BSDF times the cosine of `wi`.
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let Some(wh) = self.half_vector(wo, wi) else {
+            return 0.0;
+        };
+        let f = self.fresnel.reflectance(wo.dot(&wh), 1.0 / self.eta);
+        let d_g = self.distribution.d(&wh) * self.distribution.g(wo, wi);
+        if wi.z() > 0.0 {
+            return f * d_g / (4.0 * wo.z());
+        }
+        let denom = wo.dot(&wh) + self.eta * wi.dot(&wh);
+        (1.0 - f) * d_g * self.eta * self.eta * wi.dot(&wh).abs() * wo.dot(&wh)
+            / (wo.z() * denom * denom)
+    }
+
+    /// Density with which `sample` picks `wi`.
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let Some(wh) = self.half_vector(wo, wi) else {
+            return 0.0;
+        };
+        let f = self.fresnel.reflectance(wo.dot(&wh), 1.0 / self.eta);
+        let pdf_wh = self.distribution.pdf(wo, &wh);
+        if wi.z() > 0.0 {
+            f * pdf_wh / (4.0 * wo.dot(&wh))
+        } else {
+            let denom = wo.dot(&wh) + self.eta * wi.dot(&wh);
+            (1.0 - f) * pdf_wh * self.eta * self.eta * wi.dot(&wh).abs() / (denom * denom)
+        }
+    }
+}
+
+/// Complex index of refraction `eta + i k` of a conductor, per RGB channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: Vec3(0.143, 0.375, 1.442),
+        k: Vec3(3.983, 2.386, 1.603),
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: Vec3(0.200, 0.924, 1.102),
+        k: Vec3(3.912, 2.452, 2.142),
+    };
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: Vec3(1.657, 0.880, 0.521),
+        k: Vec3(9.224, 6.270, 4.837),
+    };
+
+    /// Fresnel reflectance of unpolarized light arriving from air at
+    /// incidence cosine `cos_i`.
+    pub fn reflectance(&self, cos_i: f64) -> Color {
+        Vec3(
+            fresnel_conductor(cos_i, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_i, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_i, self.eta.z(), self.k.z()),
+        )
+    }
+}
+
+fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = f64::sqrt(t0 * t0 + 4.0 * eta * eta * k * k);
+    let t1 = a2_plus_b2 + cos2;
+    let a = f64::sqrt(0.5 * (a2_plus_b2 + t0));
+    let t2 = 2.0 * cos_i.clamp(0.0, 1.0) * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+    (r_s + r_p) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        material::{Conductor, Material, RoughDielectric},
+        ray::{HitRecord, Ray},
+        sampler::IndependentSampler,
+    };
+
+    const UP: Vec3 = Vec3(0.0, 0.0, 1.0);
+
+    /// A hit on the z = 0 plane, facing up, by a ray arriving from `wo`
+    /// (which points away from the surface).
+    fn hit_from(material: Arc<dyn Material>, wo: &Vec3) -> (Ray, HitRecord) {
+        let ray = Ray::new(*wo, wo.mul_scalar(-1.0));
+        let hit = HitRecord::from_ray(&ray, Vec3::zero(), UP, 1.0, material);
+        (ray, hit)
+    }
+
+    fn toward(hit: &HitRecord, wi: &Vec3) -> Ray {
+        Ray::new(hit.point, *wi)
+    }
+
+    /// Uniform direction for `(u0, u1)` in [0, 1)², with z = 1 - 2 u0.
+    fn sphere_direction(u0: f64, u1: f64) -> Vec3 {
+        let z = 1.0 - 2.0 * u0;
+        let r = f64::sqrt((1.0 - z * z).max(0.0));
+        let phi = 2.0 * PI * u1;
+        Vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Integrates `f` over the sphere with a stratified grid of uniform
+    /// directions, `n` strata per side.
+    fn integrate_sphere(n: usize, mut f: impl FnMut(&Vec3) -> f64) -> f64 {
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u0 = (i as f64 + 0.5) / n as f64;
+                let u1 = (j as f64 + 0.5) / n as f64;
+                sum += f(&sphere_direction(u0, u1));
+            }
+        }
+        sum * 4.0 * PI / (n * n) as f64
+    }
+
+    /// Checks that `scatter` lands in each cell of a (z, phi) grid over the
+    /// sphere as often as the integral of `pdf` over that cell says.
+    fn check_sampling(material: Arc<dyn Material>, wo: &Vec3, pdf: impl Fn(&Ray) -> f64) {
+        const Z_BINS: usize = 8;
+        const PHI_BINS: usize = 8;
+        const SAMPLES: usize = 100_000;
+
+        let (ray, hit) = hit_from(material.clone(), wo);
+        let mut counts = [[0usize; PHI_BINS]; Z_BINS];
+        let mut sampler = IndependentSampler::new(5);
+        for _ in 0..SAMPLES {
+            let Some(scatter) = material.scatter(&ray, &hit, &mut sampler) else {
+                continue;
+            };
+            let wi = scatter.scattered.direction.normalize();
+            let z = ((1.0 - wi.z()) / 2.0 * Z_BINS as f64) as usize;
+            let phi = f64::atan2(wi.y(), wi.x()).rem_euclid(2.0 * PI);
+            let p = (phi / (2.0 * PI) * PHI_BINS as f64) as usize;
+            counts[z.min(Z_BINS - 1)][p.min(PHI_BINS - 1)] += 1;
+        }
+
+        // Integrate over each cell in (theta, phi), which resolves lobes
+        // around the poles far better than equal steps in z.
+        const STRATA: usize = 32;
+        for (zi, row) in counts.iter().enumerate() {
+            let theta0 = f64::acos(1.0 - 2.0 * zi as f64 / Z_BINS as f64);
+            let theta1 = f64::acos(1.0 - 2.0 * (zi + 1) as f64 / Z_BINS as f64);
+            let d_theta = (theta1 - theta0) / STRATA as f64;
+            let d_phi = 2.0 * PI / (PHI_BINS * STRATA) as f64;
+            for (pi, count) in row.iter().enumerate() {
+                let mut expected = 0.0;
+                for i in 0..STRATA {
+                    let theta = theta0 + (i as f64 + 0.5) * d_theta;
+                    for j in 0..STRATA {
+                        let phi = 2.0 * PI * pi as f64 / PHI_BINS as f64 + (j as f64 + 0.5) * d_phi;
+                        let wi = Vec3(
+                            theta.sin() * phi.cos(),
+                            theta.sin() * phi.sin(),
+                            theta.cos(),
+                        );
+                        expected += pdf(&toward(&hit, &wi)) * theta.sin() * d_theta * d_phi;
+                    }
+                }
+                let got = *count as f64 / SAMPLES as f64;
+                assert!(
+                    (got - expected).abs() < 0.003 + 0.03 * expected,
+                    "wo {:?}: cell ({}, {}) sampled {} of the time, pdf integrates to {}",
+                    wo,
+                    zi,
+                    pi,
+                    got,
+                    expected
+                );
+            }
+        }
+    }
+
+    fn view_directions() -> [Vec3; 3] {
+        [
+            UP,
+            Vec3(0.4, -0.3, 0.8).normalize(),
+            Vec3(-0.9, 0.2, 0.25).normalize(),
+        ]
+    }
+
+    #[test]
+    fn visible_normal_pdf_integrates_to_one() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for ggx in [Ggx::new(0.3, 0.3), Ggx::new(0.2, 0.6), Ggx::new(0.8, 0.8)] {
+            for wo in view_directions() {
+                let total = integrate_sphere(400, |wh| ggx.pdf(&wo, wh));
+                assert!((total - 1.0).abs() < 0.01, "pdf integrates to {}", total);
+
+                // Sampled normals face the viewer and lie above the surface.
+                for _ in 0..1000 {
+                    let wh = ggx.sample_wh(&wo, rng.gen(), rng.gen());
+                    assert!(wh.z() > 0.0 && wo.dot(&wh) >= -1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_samples_follow_its_pdf() {
+        for distribution in [Ggx::from_roughness(0.5, 0.0), Ggx::from_roughness(0.6, 0.7)] {
+            let conductor = Arc::new(Conductor::new(ComplexIor::GOLD, distribution));
+            for wo in view_directions() {
+                let (ray, hit) = hit_from(conductor.clone(), &wo);
+                check_sampling(conductor.clone(), &wo, |s| conductor.pdf(&ray, &hit, s));
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_samples_follow_its_pdf() {
+        let glass = Arc::new(RoughDielectric::new(1.5, Ggx::from_roughness(0.5, 0.3)));
+        for wo in view_directions() {
+            let (ray, hit) = hit_from(glass.clone(), &wo);
+            check_sampling(glass.clone(), &wo, |s| glass.pdf(&ray, &hit, s));
+
+            // And from inside, where rays can be totally internally reflected.
+            let (ray, hit) = hit_from(glass.clone(), &wo.mul_scalar(-1.0));
+            check_sampling(glass.clone(), &wo.mul_scalar(-1.0), |s| {
+                glass.pdf(&ray, &hit, s)
+            });
+        }
+    }
+
+    #[test]
+    fn white_furnace_loses_energy_but_never_gains_it() {
+        for ggx in [Ggx::new(0.3, 0.3), Ggx::new(0.2, 0.6), Ggx::new(1.0, 1.0)] {
+            for wo in view_directions() {
+                // A perfect reflector (F = 1) only loses energy to shadowing.
+                let albedo = integrate_sphere(300, |wi| {
+                    if wi.z() <= 0.0 {
+                        return 0.0;
+                    }
+                    let wh = (wo + *wi).normalize();
+                    ggx.d(&wh) * ggx.g(&wo, wi) / (4.0 * wo.z())
+                });
+                assert!(albedo <= 1.01, "reflects {} of the light", albedo);
+                // Single scattering loses more the rougher it gets, but not
+                // everything.
+                assert!(albedo > 0.25, "reflects only {} of the light", albedo);
+            }
+        }
+
+        for roughness in [0.4, 0.7] {
+            let glass = Arc::new(RoughDielectric::new(
+                1.5,
+                Ggx::from_roughness(roughness, 0.0),
+            ));
+            for wo in view_directions() {
+                for wo in [wo, wo.mul_scalar(-1.0)] {
+                    let (ray, hit) = hit_from(glass.clone(), &wo);
+                    let albedo =
+                        integrate_sphere(300, |wi| glass.eval(&ray, &hit, &toward(&hit, wi)).y());
+                    assert!(albedo <= 1.01, "glass scatters {} of the light", albedo);
+                }
+            }
+        }
+    }
+
+    /// `f(wo, wi) = eval / cos(wi)` for directions above the surface.
+    fn brdf(
+        eval: impl Fn(&Ray, &HitRecord, &Ray) -> Color,
+        material: Arc<dyn Material>,
+        wo: &Vec3,
+        wi: &Vec3,
+    ) -> Color {
+        let (ray, hit) = hit_from(material, wo);
+        eval(&ray, &hit, &toward(&hit, wi)).div_scalar(wi.z())
+    }
+
+    #[test]
+    fn reflection_is_reciprocal() {
+        let conductor = Arc::new(Conductor::new(
+            ComplexIor::COPPER,
+            Ggx::from_roughness(0.5, 0.4),
+        ));
+        let glass = Arc::new(RoughDielectric::new(1.5, Ggx::from_roughness(0.5, 0.4)));
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            let a = sphere_direction(rng.gen::<f64>() * 0.5, rng.gen());
+            let b = sphere_direction(rng.gen::<f64>() * 0.5, rng.gen());
+            let pairs = [
+                (
+                    brdf(|r, h, s| conductor.eval(r, h, s), conductor.clone(), &a, &b),
+                    brdf(|r, h, s| conductor.eval(r, h, s), conductor.clone(), &b, &a),
+                ),
+                (
+                    brdf(|r, h, s| glass.eval(r, h, s), glass.clone(), &a, &b),
+                    brdf(|r, h, s| glass.eval(r, h, s), glass.clone(), &b, &a),
+                ),
+            ];
+            for (ab, ba) in pairs {
+                assert!(
+                    (ab - ba).len() <= 1e-9 * ab.len().max(1.0),
+                    "f({:?}, {:?}) = {:?} but f({:?}, {:?}) = {:?}",
+                    a,
+                    b,
+                    ab,
+                    b,
+                    a,
+                    ba
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transmission_is_reciprocal_up_to_the_index_ratio() {
+        let ir = 1.5;
+        let glass = Arc::new(RoughDielectric::new(ir, Ggx::from_roughness(0.5, 0.0)));
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut checked = 0;
+        for _ in 0..500 {
+            let outside = sphere_direction(rng.gen::<f64>() * 0.5, rng.gen());
+            let inside = sphere_direction(0.5 + rng.gen::<f64>() * 0.5, rng.gen());
+
+            // Light from `inside` refracting out towards `outside`, and back.
+            let (ray_o, hit_o) = hit_from(glass.clone(), &outside);
+            let out = glass.eval(&ray_o, &hit_o, &toward(&hit_o, &inside));
+            let (ray_i, hit_i) = hit_from(glass.clone(), &inside);
+            let back = glass.eval(&ray_i, &hit_i, &toward(&hit_i, &outside));
+            if out.y() == 0.0 && back.y() == 0.0 {
+                continue;
+            }
+            checked += 1;
+
+            // Radiance isn't rescaled by eta² at the boundary, so the BTDF
+            // is reciprocal up to that factor: f(o, i) / ηo² = f(i, o) / ηi².
+            let f_out = out.y() / inside.z().abs();
+            let f_back = back.y() / outside.z().abs();
+            assert!(
+                (f_out - f_back * ir * ir).abs() <= 1e-9 * f_out.max(1.0),
+                "f out {} vs f back {}",
+                f_out,
+                f_back
+            );
+        }
+        assert!(checked > 100);
+    }
+}
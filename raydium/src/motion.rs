@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    instance::hit_transformed,
+    ray::{DynHittable, HitRecord, Hittable, Ray},
+    transform::{Quat, Transform},
+    vec::Vec3,
+};
+
+/// Placement of a `Keyframed` object at one instant: scaled, then rotated,
+/// then translated.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub const fn new(time: f64, translation: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation: Quat::IDENTITY,
+            scale: Vec3(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// `None` while the scale is zero on some axis, when the object has no
+    /// volume to hit.
+    fn transform(&self) -> Option<Transform> {
+        Transform::from_trs(&self.translation, &self.rotation, &self.scale)
+    }
+}
+
+/// Moves any object through its keyframes, evaluated at each ray's time:
+/// translation and scale are interpolated linearly, rotation spherically.
+/// Before the first and after the last keyframe the object holds still.
+pub struct Keyframed<T: Hittable + Send + Sync + ?Sized = DynHittable> {
+    object: Arc<T>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl<T> Keyframed<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    pub fn new(object: Arc<T>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "keyframed object needs a keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { object, keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// The interpolated keyframe at `time`.
+    pub fn keyframe_at(&self, time: f64) -> Keyframe {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return keys[0];
+        }
+        if next == keys.len() {
+            return keys[next - 1];
+        }
+        let (a, b) = (&keys[next - 1], &keys[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        Keyframe {
+            time,
+            translation: Vec3::lerp(&a.translation, &b.translation, t),
+            rotation: a.rotation.slerp(&b.rotation, t),
+            scale: Vec3::lerp(&a.scale, &b.scale, t),
+        }
+    }
+
+    pub fn translation_at(&self, time: f64) -> Vec3 {
+        self.keyframe_at(time).translation
+    }
+}
+
+impl<T> Hittable for Keyframed<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let transform = self.keyframe_at(ray.time).transform()?;
+        hit_transformed(&*self.object, &transform, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let bounds = self.object.bounding_box(time0, time1)?;
+        let inner = self
+            .keyframes
+            .iter()
+            .filter(|k| time0 < k.time && k.time < time1)
+            .copied();
+        let keys: Vec<Keyframe> = [self.keyframe_at(time0), self.keyframe_at(time1)]
+            .into_iter()
+            .chain(inner)
+            .collect();
+
+        let rotates = keys.iter().any(|k| k.rotation != keys[0].rotation);
+        if !rotates {
+            // Between keys every corner moves linearly, so the extremes are
+            // at the interval ends or at keyframes inside it.
+            return keys
+                .iter()
+                .filter_map(|k| Some(k.transform()?.bounds(&bounds)))
+                .reduce(|a, b| Aabb::surrounding(&a, &b));
+        }
+
+        // Rotation sweeps corners along arcs; bound the object by a sphere
+        // about its origin that no rotation can leave.
+        let reach = [bounds.min, bounds.max]
+            .iter()
+            .map(|c| c.x().abs().max(c.y().abs()).max(c.z().abs()))
+            .fold(0.0, f64::max)
+            * f64::sqrt(3.0);
+        keys.iter()
+            .map(|k| {
+                let max_scale = k
+                    .scale
+                    .x()
+                    .abs()
+                    .max(k.scale.y().abs())
+                    .max(k.scale.z().abs());
+                let r = reach * max_scale;
+                let r = Vec3(r, r, r);
+                Aabb::new(k.translation - r, k.translation + r)
+            })
+            .reduce(|a, b| Aabb::surrounding(&a, &b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{geom::Sphere, material::Lambertian};
+
+    fn sphere(center: Vec3, radius: f64) -> Arc<Sphere> {
+        let material = Arc::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(material, center, radius))
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn translation_is_interpolated_and_held_outside_the_keys() {
+        let moving = Keyframed::new(
+            sphere(Vec3(0.0, 0.0, 0.0), 0.5),
+            vec![
+                Keyframe::new(1.0, Vec3(4.0, 0.0, 0.0)),
+                Keyframe::new(0.0, Vec3(0.0, 0.0, 0.0)),
+            ],
+        );
+        assert_near(moving.translation_at(0.25), Vec3(1.0, 0.0, 0.0));
+        assert_near(moving.translation_at(-1.0), Vec3(0.0, 0.0, 0.0));
+        assert_near(moving.translation_at(2.0), Vec3(4.0, 0.0, 0.0));
+
+        let ray = |time| Ray::new_timed(Vec3(2.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0), time);
+        let hit = moving.hit(&ray(0.5), 0.001, f64::INFINITY).unwrap();
+        assert_near(hit.point, Vec3(2.0, 0.0, 0.5));
+        assert!(moving.hit(&ray(0.0), 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bounds_cover_every_intermediate_placement() {
+        let zigzag = Keyframed::new(
+            sphere(Vec3(0.0, 0.0, 0.0), 0.5),
+            vec![
+                Keyframe::new(0.0, Vec3(0.0, 0.0, 0.0)),
+                Keyframe::new(0.5, Vec3(2.0, 3.0, 0.0)),
+                Keyframe::new(1.0, Vec3(4.0, 0.0, -1.0)),
+            ],
+        );
+        // Bounds over part of the path must still reach the middle key.
+        let bounds = zigzag.bounding_box(0.25, 0.75).unwrap();
+        for i in 0..=16 {
+            let time = 0.25 + 0.5 * i as f64 / 16.0;
+            let center = zigzag.translation_at(time);
+            for offset in [
+                Vec3(0.5, 0.0, 0.0),
+                Vec3(-0.5, 0.0, 0.0),
+                Vec3(0.0, 0.5, 0.0),
+                Vec3(0.0, -0.5, 0.0),
+                Vec3(0.0, 0.0, 0.5),
+                Vec3(0.0, 0.0, -0.5),
+            ] {
+                let p = center + offset;
+                assert!(
+                    bounds.contains(&p),
+                    "{:?} at t={} outside {:?}",
+                    p,
+                    time,
+                    bounds
+                );
+            }
+        }
+        assert!(!bounds.contains(&Vec3(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn rotation_is_slerped_between_keys() {
+        let quarter = Quat::from_axis_angle(&Vec3(0.0, 1.0, 0.0), PI / 2.0);
+        let spinning = Keyframed::new(
+            sphere(Vec3(2.0, 0.0, 0.0), 0.5),
+            vec![
+                Keyframe::new(0.0, Vec3(0.0, 0.0, 0.0)),
+                Keyframe::new(1.0, Vec3(0.0, 0.0, 0.0)).with_rotation(quarter),
+            ],
+        );
+        let half = spinning.keyframe_at(0.5).rotation;
+        let diagonal = f64::sqrt(0.5);
+        assert_near(
+            half.rotate(&Vec3(1.0, 0.0, 0.0)),
+            Vec3(diagonal, 0.0, -diagonal),
+        );
+
+        // The sphere's centre has swung halfway to -z.
+        let ray = Ray::new_timed(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, -1.0), 0.5);
+        let hit = spinning.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_near(hit.point, Vec3(1.5 * diagonal, 0.0, -1.5 * diagonal));
+    }
+
+    #[test]
+    fn rotating_bounds_cover_every_intermediate_placement() {
+        let half_turn = Quat::from_axis_angle(&Vec3(0.0, 1.0, 0.0), PI);
+        let orbiting = Keyframed::new(
+            sphere(Vec3(2.0, 0.0, 0.0), 0.5),
+            vec![
+                Keyframe::new(0.0, Vec3(0.0, 0.0, 0.0)),
+                Keyframe::new(1.0, Vec3(0.0, 1.0, 0.0)).with_rotation(half_turn),
+            ],
+        );
+        let bounds = orbiting.bounding_box(0.0, 1.0).unwrap();
+        for i in 0..=16 {
+            let time = i as f64 / 16.0;
+            let key = orbiting.keyframe_at(time);
+            let center = key.rotation.rotate(&Vec3(2.0, 0.0, 0.0)) + key.translation;
+            for offset in [
+                Vec3(0.5, 0.0, 0.0),
+                Vec3(-0.5, 0.0, 0.0),
+                Vec3(0.0, 0.5, 0.0),
+                Vec3(0.0, -0.5, 0.0),
+                Vec3(0.0, 0.0, 0.5),
+                Vec3(0.0, 0.0, -0.5),
+            ] {
+                let p = center + offset;
+                assert!(
+                    bounds.contains(&p),
+                    "{:?} at t={} outside {:?}",
+                    p,
+                    time,
+                    bounds
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_scale_hides_the_object_for_that_instant() {
+        let flipping = Keyframed::new(
+            sphere(Vec3(0.0, 0.0, 0.0), 1.0),
+            vec![
+                Keyframe::new(0.0, Vec3(0.0, 0.0, 0.0)),
+                Keyframe::new(1.0, Vec3(0.0, 0.0, 0.0)).with_scale(Vec3(-1.0, 1.0, 1.0)),
+            ],
+        );
+        let ray = |time| Ray::new_timed(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0), time);
+        assert!(flipping.hit(&ray(0.5), 0.001, f64::INFINITY).is_none());
+        assert!(flipping.hit(&ray(0.25), 0.001, f64::INFINITY).is_some());
+        assert!(flipping.bounding_box(0.0, 1.0).is_some());
+    }
+}
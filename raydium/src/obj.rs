@@ -0,0 +1,531 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::{
+    material::{Dielectric, Lambertian, Material, Metal},
+    mesh::{MeshData, TriangleMesh},
+    ray::HitList,
+    texture::{ImageTexture, SolidColor, Texture},
+    vec::Vec3,
+};
+
+/// One `g`/`o` group of an OBJ file, split further wherever `usemtl` changes.
+pub struct ObjGroup {
+    pub name: String,
+    pub material_name: Option<String>,
+    pub mesh: Arc<TriangleMesh>,
+}
+
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+}
+
+impl ObjModel {
+    pub fn num_triangles(&self) -> usize {
+        self.groups.iter().map(|g| g.mesh.num_triangles()).sum()
+    }
+
+    pub fn into_hit_list(self) -> HitList {
+        let mut list: HitList = HitList::new();
+        for group in self.groups {
+            list.push(group.mesh);
+        }
+        list
+    }
+}
+
+/// Raw MTL parameters, before they are mapped onto one of our materials.
+#[derive(Debug, Clone)]
+pub struct MtlParams {
+    pub kd: Vec3,
+    pub ks: Vec3,
+    pub ns: f64,
+    pub ni: f64,
+    pub d: f64,
+    /// Transmission filter (`Tf`), the tint of refracted light.
+    pub tf: Vec3,
+    pub illum: u32,
+    /// Diffuse colour map (`map_Kd`), replacing `kd` where present.
+    pub map_kd: Option<PathBuf>,
+}
+
+impl Default for MtlParams {
+    fn default() -> Self {
+        Self {
+            kd: Vec3(0.8, 0.8, 0.8),
+            ks: Vec3::zero(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            tf: Vec3::WHITE,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlParams {
+    /// Picks the closest of `Dielectric`, `Metal` and `Lambertian`:
+    /// transparent or refracting illumination models become glass, models with
+    /// ray traced reflection become metal (fuzz derived from the Phong exponent),
+    /// everything else is diffuse. A `map_Kd` that fails to load is reported
+    /// and replaced by `kd`.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        let refracts = matches!(self.illum, 4 | 6 | 7 | 9);
+        let reflects = matches!(self.illum, 3 | 5 | 8);
+        if self.d < 1.0 || refracts {
+            Arc::new(Dielectric::new(self.ni).with_tint(self.tf))
+        } else if reflects {
+            let albedo = if self.ks.is_near_zero() {
+                self.kd
+            } else {
+                self.ks
+            };
+            let fuzz = f64::sqrt(2.0 / (self.ns + 2.0));
+            Arc::new(Metal::new(albedo, fuzz))
+        } else {
+            Arc::new(Lambertian::textured(self.diffuse_texture()))
+        }
+    }
+
+    fn diffuse_texture(&self) -> Arc<dyn Texture> {
+        if let Some(path) = &self.map_kd {
+            match ImageTexture::load(path) {
+                Ok(texture) => return Arc::new(texture),
+                Err(e) => log::warn!("{:#}; using Kd instead", e),
+            }
+        }
+        Arc::new(SolidColor::new(self.kd))
+    }
+}
+
+pub fn load_mtl(path: &Path) -> anyhow::Result<HashMap<String, MtlParams>> {
+    let src = fs::read_to_string(path)
+        .with_context(|| format!("failed to read MTL file {}", path.display()))?;
+    let mut materials =
+        parse_mtl(&src).with_context(|| format!("in MTL file {}", path.display()))?;
+    // Texture paths are relative to the MTL file.
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    for params in materials.values_mut() {
+        if let Some(map) = params.map_kd.as_mut() {
+            *map = base_dir.join(&*map);
+        }
+    }
+    Ok(materials)
+}
+
+pub fn parse_mtl(src: &str) -> anyhow::Result<HashMap<String, MtlParams>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlParams)> = None;
+
+    for (lineno, line) in src.lines().enumerate() {
+        let lineno = lineno + 1;
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword.starts_with('#') {
+            continue;
+        }
+        if keyword == "newmtl" {
+            if let Some((name, params)) = current.take() {
+                materials.insert(name, params);
+            }
+            let name = args.join(" ");
+            if name.is_empty() {
+                bail!("line {}: newmtl without a name", lineno);
+            }
+            current = Some((name, MtlParams::default()));
+            continue;
+        }
+
+        let Some((_, params)) = current.as_mut() else {
+            bail!("line {}: `{}` before any newmtl", lineno, keyword);
+        };
+        match keyword {
+            "Kd" => params.kd = parse_vec3(&args, lineno)?,
+            "Ks" => params.ks = parse_vec3(&args, lineno)?,
+            "Ns" => params.ns = parse_f64(&args, 0, lineno)?,
+            "Ni" => params.ni = parse_f64(&args, 0, lineno)?,
+            "d" => params.d = parse_f64(&args, 0, lineno)?,
+            "Tr" => params.d = 1.0 - parse_f64(&args, 0, lineno)?,
+            "Tf" => params.tf = parse_vec3(&args, lineno)?,
+            "illum" => {
+                params.illum = args
+                    .first()
+                    .ok_or_else(|| anyhow!("line {}: illum missing value", lineno))?
+                    .parse()
+                    .map_err(|e| anyhow!("line {}: bad illum value: {}", lineno, e))?
+            }
+            // Options such as `-s` come before the file name.
+            "map_Kd" => {
+                let file = args
+                    .last()
+                    .ok_or_else(|| anyhow!("line {}: map_Kd missing file name", lineno))?;
+                params.map_kd = Some(PathBuf::from(file));
+            }
+            // Ka, Ke, other texture maps etc. have no counterpart in our materials yet.
+            _ => {}
+        }
+    }
+
+    if let Some((name, params)) = current {
+        materials.insert(name, params);
+    }
+    Ok(materials)
+}
+
+/// Loads a Wavefront OBJ file (and any MTL libraries it references) into one
+/// `TriangleMesh` per group/material. Polygons are fan-triangulated; faces
+/// without `usemtl` get `default_material`.
+pub fn load_obj(path: &Path, default_material: Arc<dyn Material>) -> anyhow::Result<ObjModel> {
+    let src = fs::read_to_string(path)
+        .with_context(|| format!("failed to read OBJ file {}", path.display()))?;
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    parse_obj(&src, &base_dir, default_material)
+        .with_context(|| format!("in OBJ file {}", path.display()))
+}
+
+/// Parses OBJ source; `mtllib` paths are resolved relative to `base_dir`.
+pub fn parse_obj(
+    src: &str,
+    base_dir: &Path,
+    default_material: Arc<dyn Material>,
+) -> anyhow::Result<ObjModel> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+
+    let mut mtl_params: HashMap<String, MtlParams> = HashMap::new();
+    let mut material_cache: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    let mut groups = Vec::new();
+    let mut builder = GroupBuilder::new("default".into(), None);
+
+    for (lineno, line) in src.lines().enumerate() {
+        let lineno = lineno + 1;
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&args, lineno)?),
+            "vn" => normals.push(parse_vec3(&args, lineno)?),
+            "vt" => uvs.push((
+                parse_f64(&args, 0, lineno)?,
+                if args.len() > 1 {
+                    parse_f64(&args, 1, lineno)?
+                } else {
+                    0.0
+                },
+            )),
+            "f" => {
+                if args.len() < 3 {
+                    bail!("line {}: face needs at least 3 vertices", lineno);
+                }
+                let corners = args
+                    .iter()
+                    .map(|a| {
+                        parse_face_vertex(a, positions.len(), uvs.len(), normals.len())
+                            .map_err(|e| anyhow!("line {}: {}", lineno, e))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let corners: Vec<u32> = corners
+                    .into_iter()
+                    .map(|c| builder.vertex(c, &positions, &uvs, &normals))
+                    .collect();
+                for i in 1..corners.len() - 1 {
+                    builder
+                        .indices
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let name = if args.is_empty() {
+                    "default".into()
+                } else {
+                    args.join(" ")
+                };
+                let material = builder.material_name.clone();
+                let done = std::mem::replace(&mut builder, GroupBuilder::new(name, material));
+                groups.extend(done.finish(&mtl_params, &mut material_cache, &default_material)?);
+            }
+            "usemtl" => {
+                let mut material = Some(args.join(" "));
+                if let Some(m) = material.as_ref().filter(|m| !mtl_params.contains_key(*m)) {
+                    log::warn!("line {}: unknown material `{}`, using default", lineno, m);
+                    material = None;
+                }
+                let name = builder.name.clone();
+                let done = std::mem::replace(&mut builder, GroupBuilder::new(name, material));
+                groups.extend(done.finish(&mtl_params, &mut material_cache, &default_material)?);
+            }
+            "mtllib" => {
+                for lib in args {
+                    let lib_path: PathBuf = base_dir.join(lib);
+                    let parsed = load_mtl(&lib_path)
+                        .with_context(|| format!("line {}: loading mtllib", lineno))?;
+                    mtl_params.extend(parsed);
+                }
+            }
+            // Comments, smoothing groups and free-form geometry are ignored.
+            _ => {}
+        }
+    }
+    groups.extend(builder.finish(&mtl_params, &mut material_cache, &default_material)?);
+
+    Ok(ObjModel { groups })
+}
+
+/// Position/uv/normal indices of one face corner, already resolved to
+/// zero-based indices.
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+fn parse_face_vertex(s: &str, npos: usize, nuv: usize, nnorm: usize) -> anyhow::Result<FaceVertex> {
+    let resolve = |field: &str, count: usize, what: &str| -> anyhow::Result<usize> {
+        let i: i64 = field
+            .parse()
+            .map_err(|_| anyhow!("bad {} index `{}`", what, field))?;
+        // OBJ indices are 1-based, negative values count back from the end.
+        let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+        if i == 0 || resolved < 0 || resolved >= count as i64 {
+            bail!("{} index {} out of range ({} defined)", what, i, count);
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut parts = s.split('/');
+    let pos = resolve(parts.next().unwrap_or(""), npos, "vertex")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(f) => Some(resolve(f, nuv, "texcoord")?),
+    };
+    let norm = match parts.next() {
+        Some("") | None => None,
+        Some(f) => Some(resolve(f, nnorm, "normal")?),
+    };
+    Ok((pos, uv, norm))
+}
+
+struct GroupBuilder {
+    name: String,
+    material_name: Option<String>,
+    remap: HashMap<FaceVertex, u32>,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    missing_normals: bool,
+    missing_uvs: bool,
+    indices: Vec<[u32; 3]>,
+}
+
+impl GroupBuilder {
+    fn new(name: String, material_name: Option<String>) -> Self {
+        Self {
+            name,
+            material_name,
+            remap: HashMap::new(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            missing_normals: false,
+            missing_uvs: false,
+            indices: Vec::new(),
+        }
+    }
+
+    /// Maps a position/uv/normal triple onto a single mesh vertex index.
+    fn vertex(
+        &mut self,
+        key: FaceVertex,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> u32 {
+        if let Some(&i) = self.remap.get(&key) {
+            return i;
+        }
+        let (p, uv, n) = key;
+        let i = self.positions.len() as u32;
+        self.positions.push(positions[p]);
+        self.uvs.push(uv.map_or((0.0, 0.0), |uv| uvs[uv]));
+        self.normals.push(n.map_or(Vec3::zero(), |n| normals[n]));
+        self.missing_uvs |= uv.is_none();
+        self.missing_normals |= n.is_none();
+        self.remap.insert(key, i);
+        i
+    }
+
+    fn finish(
+        self,
+        mtl_params: &HashMap<String, MtlParams>,
+        material_cache: &mut HashMap<String, Arc<dyn Material>>,
+        default_material: &Arc<dyn Material>,
+    ) -> anyhow::Result<Option<ObjGroup>> {
+        if self.indices.is_empty() {
+            return Ok(None);
+        }
+
+        let material = match &self.material_name {
+            Some(name) => match material_cache.get(name) {
+                Some(m) => m.clone(),
+                None => {
+                    let params = mtl_params
+                        .get(name)
+                        .ok_or_else(|| anyhow!("unknown material `{}`", name))?;
+                    let m = params.to_material();
+                    material_cache.insert(name.clone(), m.clone());
+                    m
+                }
+            },
+            None => default_material.clone(),
+        };
+
+        // Partial attributes can't be interpolated, so drop them for the group.
+        let normals = if self.missing_normals {
+            Vec::new()
+        } else {
+            self.normals
+        };
+        let uvs = if self.missing_uvs {
+            Vec::new()
+        } else {
+            self.uvs
+        };
+
+        let mesh = TriangleMesh::new(MeshData {
+            positions: self.positions,
+            normals,
+            uvs,
+            indices: self.indices,
+            material,
+        })?;
+        Ok(Some(ObjGroup {
+            name: self.name,
+            material_name: self.material_name,
+            mesh: Arc::new(mesh),
+        }))
+    }
+}
+
+fn parse_f64(args: &[&str], i: usize, lineno: usize) -> anyhow::Result<f64> {
+    let s = args
+        .get(i)
+        .ok_or_else(|| anyhow!("line {}: expected at least {} values", lineno, i + 1))?;
+    s.parse()
+        .map_err(|_| anyhow!("line {}: bad number `{}`", lineno, s))
+}
+
+fn parse_vec3(args: &[&str], lineno: usize) -> anyhow::Result<Vec3> {
+    Ok(Vec3(
+        parse_f64(args, 0, lineno)?,
+        parse_f64(args, 1, lineno)?,
+        parse_f64(args, 2, lineno)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> anyhow::Result<ObjModel> {
+        parse_obj(src, Path::new(""), Arc::new(Lambertian::new(Vec3::WHITE)))
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let model = parse(&format!("{}v 0.5 1.5 0\nf 1 2 3 5 4\n", SQUARE)).unwrap();
+        assert_eq!(model.groups.len(), 1);
+        let data = model.groups[0].mesh.data();
+        assert_eq!(data.indices, [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert_eq!(data.positions[3], Vec3(0.5, 1.5, 0.0));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let model = parse(&format!("{}f -4 -3 -2\nf 1/ -2 -1//\n", SQUARE)).unwrap();
+        let data = model.groups[0].mesh.data();
+        let face = |i: usize| data.indices[i].map(|v| data.positions[v as usize]);
+        assert_eq!(
+            face(0),
+            [Vec3(0., 0., 0.), Vec3(1., 0., 0.), Vec3(1., 1., 0.)]
+        );
+        assert_eq!(
+            face(1),
+            [Vec3(0., 0., 0.), Vec3(1., 1., 0.), Vec3(0., 1., 0.)]
+        );
+
+        let Err(err) = parse(&format!("{}f 1 2 3\nf -5 1 2\n", SQUARE)) else {
+            panic!("out of range index accepted");
+        };
+        assert_eq!(
+            err.to_string(),
+            "line 6: vertex index -5 out of range (4 defined)"
+        );
+    }
+
+    #[test]
+    fn groups_split_at_g_and_usemtl() {
+        let dir = std::env::temp_dir().join(format!("raydium-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("red.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+        let src = format!(
+            "mtllib red.mtl\n{}g first\nusemtl red\nf 1 2 3\ng second\nf 1 3 4\nusemtl missing\nf 2 3 4\n",
+            SQUARE
+        );
+        let model = parse_obj(&src, &dir, Arc::new(Lambertian::new(Vec3::WHITE)));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let groups: Vec<_> = model
+            .unwrap()
+            .groups
+            .iter()
+            .map(|g| {
+                (
+                    g.name.clone(),
+                    g.material_name.clone(),
+                    g.mesh.num_triangles(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("first".into(), Some("red".into()), 1),
+                ("second".into(), Some("red".into()), 1),
+                ("second".into(), None, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn mtl_parameters_are_read_per_material() {
+        let materials = parse_mtl(
+            "# glass and gold\nnewmtl glass\nNi 1.45\nd 0.5\nillum 7\n\nnewmtl gold\nKs 1 0.8 0.3\nNs 200\nillum 3\n",
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 2);
+        let glass = &materials["glass"];
+        assert_eq!((glass.ni, glass.d, glass.illum), (1.45, 0.5, 7));
+        let gold = &materials["gold"];
+        assert_eq!(
+            (gold.ks, gold.ns, gold.illum),
+            (Vec3(1.0, 0.8, 0.3), 200.0, 3)
+        );
+
+        let err = parse_mtl("Kd 1 1 1\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: `Kd` before any newmtl");
+    }
+}
@@ -0,0 +1,62 @@
+use crate::vec::Vec3;
+
+/// Orthonormal basis. Materials use it to sample in a local frame whose `w`
+/// axis is the surface normal and to bring directions back to world space.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Default for Onb {
+    fn default() -> Self {
+        Self {
+            u: Vec3(1.0, 0.0, 0.0),
+            v: Vec3(0.0, 1.0, 0.0),
+            w: Vec3(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl Onb {
+    /// Any basis around `w`, chosen without branching on near-parallel axes
+    /// (Duff et al., "Building an Orthonormal Basis, Revisited", 2017).
+    pub fn from_w(w: &Vec3) -> Self {
+        let w = w.normalize();
+        let sign = 1.0f64.copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        Self {
+            u: Vec3(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()),
+            v: Vec3(b, sign + w.y() * w.y() * a, -w.y()),
+            w,
+        }
+    }
+
+    /// Basis around `w` with `u` as close as possible to `u_hint`, e.g. a
+    /// surface tangent. Falls back to `from_w` if the hint is parallel to `w`.
+    pub fn from_wu(w: &Vec3, u_hint: &Vec3) -> Self {
+        let w = w.normalize();
+        let u = *u_hint - w.mul_scalar(w.dot(u_hint));
+        if u.len_sq() < 1e-16 {
+            return Self::from_w(&w);
+        }
+        let u = u.normalize();
+        Self {
+            u,
+            v: Vec3::cross(&w, &u),
+            w,
+        }
+    }
+
+    /// Local coordinates `a` to world space.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u.mul_scalar(a.x()) + self.v.mul_scalar(a.y()) + self.w.mul_scalar(a.z())
+    }
+
+    /// World space direction `a` to local coordinates.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
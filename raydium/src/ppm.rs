@@ -1,6 +1,6 @@
 use std::io::Write;
 
-use crate::{vec::{Vec3, Color}};
+use crate::vec::{Vec3, Color};
 pub struct Image {
     width: u32,
     height: u32,
@@ -0,0 +1,320 @@
+use std::{f64::consts::PI, ops::Neg, sync::Arc};
+
+use crate::{
+    material::{Fresnel, Material, ScatterResult},
+    microfacet::{DielectricInterface, Ggx},
+    ray::{HitRecord, NormalFace, Ray},
+    sampler::Sampler,
+    texture::{Channel, ChannelTexture, ScaledTexture, SolidColor, Texture},
+    vec::{Color, Vec3},
+};
+
+/// One material covering plastics, metals, glass, cloth and lacquered
+/// surfaces, after Burley's "Physically Based Shading at Disney" (2012).
+///
+/// Every parameter is a texture; scalar parameters read the first channel
+/// and are meant to lie in [0, 1]. The layers are a Burley diffuse base
+/// (blended towards a subsurface approximation, with a sheen at grazing
+/// angles), a GGX specular lobe that tints towards `base_color` as the
+/// surface becomes metallic, rough GGX transmission and a GGX clearcoat.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Dielectric reflectance at normal incidence, scaled so 0.5 is 4%.
+    pub specular: Arc<dyn Texture>,
+    /// Tints dielectric specular towards the base colour.
+    pub specular_tint: Arc<dyn Texture>,
+    /// Retro-reflective rim for cloth.
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    /// Strength of a second, colourless specular layer.
+    pub clearcoat: Arc<dyn Texture>,
+    /// Smoothness of the clearcoat: 0 satin, 1 gloss.
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    /// Share of the non-metallic base that is transmitted like rough glass.
+    pub transmission: Arc<dyn Texture>,
+    /// Blends the diffuse base towards a flattened subsurface look.
+    pub subsurface: Arc<dyn Texture>,
+    /// Index of refraction used for transmission.
+    pub ior: f64,
+}
+
+/// Constant scalar texture.
+pub fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(Vec3(value, value, value)))
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Arc::new(SolidColor::new(Vec3(0.8, 0.8, 0.8))),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            subsurface: constant(0.0),
+            ior: 1.5,
+        }
+    }
+}
+
+/// Inputs of a glTF 2.0 `pbrMetallicRoughness` material, plus the
+/// `KHR_materials_transmission` and `KHR_materials_ior` extensions. Textures
+/// multiply their factors, as in glTF.
+pub struct GltfMetallicRoughness {
+    pub base_color_factor: Color,
+    pub base_color_texture: Option<Arc<dyn Texture>>,
+    pub metallic_factor: f64,
+    pub roughness_factor: f64,
+    /// Roughness in the green channel, metalness in blue.
+    pub metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    pub transmission_factor: f64,
+    pub ior: f64,
+}
+
+impl Default for GltfMetallicRoughness {
+    fn default() -> Self {
+        Self {
+            base_color_factor: Color::WHITE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            transmission_factor: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl From<GltfMetallicRoughness> for Principled {
+    fn from(gltf: GltfMetallicRoughness) -> Self {
+        let base_color: Arc<dyn Texture> = match gltf.base_color_texture {
+            Some(texture) => Arc::new(ScaledTexture::new(texture, gltf.base_color_factor)),
+            None => Arc::new(SolidColor::new(gltf.base_color_factor)),
+        };
+        let packed = |channel, factor: f64| -> Arc<dyn Texture> {
+            match &gltf.metallic_roughness_texture {
+                Some(texture) => Arc::new(ScaledTexture::new(
+                    Arc::new(ChannelTexture::new(texture.clone(), channel)),
+                    Vec3(factor, factor, factor),
+                )),
+                None => constant(factor),
+            }
+        };
+        // glTF's fixed 4% dielectric reflectance is `specular` 0.5.
+        Self {
+            base_color,
+            metallic: packed(Channel::B, gltf.metallic_factor),
+            roughness: packed(Channel::G, gltf.roughness_factor),
+            transmission: constant(gltf.transmission_factor),
+            ior: gltf.ior,
+            ..Default::default()
+        }
+    }
+}
+
+/// The parameters looked up at one hit point, and the lobes they define.
+struct Lobes {
+    base_color: Color,
+    roughness: f64,
+    subsurface: f64,
+    sheen: Color,
+    specular_f0: Color,
+    specular: Ggx,
+    clearcoat: Ggx,
+    interface: DielectricInterface,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+    clearcoat_weight: f64,
+}
+
+impl Lobes {
+    fn new(material: &Principled, hit: &HitRecord) -> Self {
+        let scalar = |t: &Arc<dyn Texture>| t.value(hit.u, hit.v, &hit.point).x();
+        let base_color = material.base_color.value(hit.u, hit.v, &hit.point);
+        let metallic = scalar(&material.metallic);
+        let roughness = scalar(&material.roughness);
+        let transmission = scalar(&material.transmission);
+
+        // Hue and saturation of the base colour, at unit luminance.
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 {
+            base_color.div_scalar(luminance)
+        } else {
+            Color::WHITE
+        };
+        let dielectric_f0 = Vec3::lerp(&Color::WHITE, &tint, scalar(&material.specular_tint))
+            .mul_scalar(0.08 * scalar(&material.specular));
+        let sheen = Vec3::lerp(&Color::WHITE, &tint, scalar(&material.sheen_tint))
+            .mul_scalar(scalar(&material.sheen));
+        let gloss = scalar(&material.clearcoat_gloss);
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * gloss;
+
+        Self {
+            base_color,
+            roughness,
+            subsurface: scalar(&material.subsurface),
+            sheen,
+            specular_f0: Vec3::lerp(&dielectric_f0, &base_color, metallic),
+            specular: Ggx::from_roughness(roughness, 0.0),
+            clearcoat: Ggx::new(clearcoat_alpha, clearcoat_alpha),
+            interface: DielectricInterface {
+                distribution: Ggx::from_roughness(roughness, 0.0),
+                eta: match hit.normal_face {
+                    NormalFace::FrontOuter => material.ior,
+                    NormalFace::BackInner => 1.0 / material.ior,
+                },
+                fresnel: Fresnel::Exact,
+            },
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            transmission_weight: (1.0 - metallic) * transmission,
+            clearcoat_weight: 0.25 * scalar(&material.clearcoat),
+        }
+    }
+
+    /// Probabilities of sampling the diffuse, specular, transmission and
+    /// clearcoat lobes when looking from `wo`, roughly by their energy.
+    fn sampling_weights(&self, wo: &Vec3) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight,
+            // Floored so the Fresnel gain of weak specular at grazing angles
+            // is still found.
+            self.specular_weight * schlick(&self.specular_f0, wo.z()).luminance().max(0.1),
+            self.transmission_weight,
+            self.clearcoat_weight * schlick_weight(wo.z()).max(0.04),
+        ];
+        let total: f64 = weights.iter().sum();
+        if total > 0.0 {
+            weights.map(|w| w / total)
+        } else {
+            weights
+        }
+    }
+
+    /// The BSDF times the cosine of `wi`.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let mut value = Color::BLACK;
+        if self.transmission_weight > 0.0 {
+            let filter = if wi.z() < 0.0 {
+                self.base_color
+            } else {
+                Color::WHITE
+            };
+            value =
+                value + filter.mul_scalar(self.transmission_weight * self.interface.eval(wo, wi));
+        }
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return value;
+        }
+
+        let wh = (*wo + *wi).normalize();
+        let cos_d = wi.dot(&wh);
+        if self.diffuse_weight > 0.0 {
+            let (fl, fv) = (schlick_weight(wi.z()), schlick_weight(wo.z()));
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            let fss90 = self.roughness * cos_d * cos_d;
+            let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+            let ss = 1.25 * (fss * (1.0 / (wi.z() + wo.z()) - 0.5) + 0.5);
+            let diffuse = self
+                .base_color
+                .mul_scalar((fd + (ss - fd) * self.subsurface) / PI);
+            let sheen = self.sheen.mul_scalar(schlick_weight(cos_d));
+            value = value + (diffuse + sheen).mul_scalar(self.diffuse_weight * wi.z());
+        }
+        if self.specular_weight > 0.0 {
+            let f = schlick(&self.specular_f0, cos_d);
+            value = value + f.mul_scalar(self.specular_weight * self.specular.reflection(wo, wi));
+        }
+        if self.clearcoat_weight > 0.0 {
+            let f = 0.04 + 0.96 * schlick_weight(cos_d);
+            let c = self.clearcoat_weight * f * self.clearcoat.reflection(wo, wi);
+            value = value + Vec3(c, c, c);
+        }
+        value
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let [diffuse, specular, transmission, clearcoat] = self.sampling_weights(wo);
+        let mut pdf = transmission * self.interface.pdf(wo, wi);
+        if wi.z() > 0.0 {
+            pdf += diffuse * wi.z() / PI
+                + specular * self.specular.reflection_pdf(wo, wi)
+                + clearcoat * self.clearcoat.reflection_pdf(wo, wi);
+        }
+        pdf
+    }
+
+    fn sample(&self, wo: &Vec3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        let [diffuse, specular, transmission, _] = self.sampling_weights(wo);
+        let (u0, u1) = sampler.next_2d();
+        let choice = sampler.next_1d();
+        if choice < diffuse {
+            Some(Vec3::new_rand_cosine_direction(sampler))
+        } else if choice < diffuse + specular {
+            self.specular.sample_reflection(wo, u0, u1)
+        } else if choice < diffuse + specular + transmission {
+            self.interface.sample(wo, u0, u1, sampler.next_1d())
+        } else {
+            self.clearcoat.sample_reflection(wo, u0, u1)
+        }
+    }
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
+fn schlick(f0: &Color, cos: f64) -> Color {
+    *f0 + (Color::WHITE - *f0).mul_scalar(schlick_weight(cos))
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterResult> {
+        let wo = hit.shading.to_local(&ray.direction.normalize().neg());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let lobes = Lobes::new(self, hit);
+        let wi = lobes.sample(&wo, sampler)?;
+        // Weighting by the density of all lobes together (one-sample MIS)
+        // keeps the estimate low in variance whichever lobe was picked.
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterResult {
+            scattered: Ray::new_timed(hit.point, hit.shading.local(&wi), ray.time),
+            attenuation: lobes.eval(&wo, &wi).div_scalar(pdf),
+        })
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Color {
+        let wo = hit.shading.to_local(&ray.direction.normalize().neg());
+        let wi = hit.shading.to_local(&scattered.direction.normalize());
+        Lobes::new(self, hit).eval(&wo, &wi)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
+        let wo = hit.shading.to_local(&ray.direction.normalize().neg());
+        let wi = hit.shading.to_local(&scattered.direction.normalize());
+        Lobes::new(self, hit).pdf(&wo, &wi)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color {
+        self.base_color.value(hit.u, hit.v, &hit.point)
+    }
+}
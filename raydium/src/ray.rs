@@ -1,45 +1,31 @@
-use std::{ops::Neg, rc::Rc, sync::Arc};
+use std::{ops::Neg, sync::Arc};
 
-use crate::{
-    material::{Material, ScatterResult},
-    vec::{Color, Vec3},
-    world::Camera,
-};
+use crate::{aabb::Aabb, material::Material, onb::Onb, sampler::Sampler, vec::Vec3};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Moment within the camera shutter interval the ray was cast at, used
+    /// to place moving objects.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self::new_timed(origin, direction, 0.0)
     }
 
-    pub fn at(&self, t: f64) -> Vec3 {
-        self.origin + self.direction.mul_scalar(t)
-    }
-
-    pub fn color<T: Hittable + Send + Sync>(&self, world: &HitList<T>, depth: u32) -> Vec3 {
-        if depth <= 0 {
-            return Vec3::zero();
+    pub fn new_timed(origin: Vec3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
         }
+    }
 
-        if let Some(hit) = world.hit(self, 0.001, f64::INFINITY) {
-            if let Some(sr) = hit.material.scatter(self, &hit) {
-                sr.attenuation * sr.scattered.color(world, depth - 1)
-            } else {
-                Vec3::zero()
-            }
-            // let target = hit.point + hit.normal + Vec3::new_rand_unit_sphere();
-            // Ray::color(&Ray::new(hit.point, target - hit.point), &world, depth - 1).mul_scalar(0.5)
-            //(hit.normal + Color::WHITE).mul_scalar(0.5)
-        } else {
-            let dir = self.direction.normalize();
-            let t = 0.5 * (dir.y() + 1.0);
-            Vec3::lerp(&Color::WHITE, &Vec3(0.5, 0.7, 1.0), t)
-        }
+    pub fn at(&self, t: f64) -> Vec3 {
+        self.origin + self.direction.mul_scalar(t)
     }
 }
 
@@ -49,21 +35,37 @@ pub enum NormalFace {
     BackInner,
 }
 
+/// Everything shading needs to know about a ray/surface intersection.
+///
+/// `normal` faces against the incoming ray. `(u, v)` are the surface
+/// coordinates, `dpdu`/`dpdv` how the point moves with them, and `shading` is
+/// an orthonormal frame with `w` along `normal` and `u` along `dpdu`.
 #[derive(Clone)]
 pub struct HitRecord {
     pub point: Vec3,
     pub normal: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub shading: Onb,
     pub normal_face: NormalFace,
     pub material: Arc<dyn Material>,
 }
 
 impl HitRecord {
     pub fn new(point: Vec3, normal: Vec3, t: f64, material: Arc<dyn Material>) -> Self {
+        let shading = Onb::from_w(&normal);
         Self {
             point,
             normal,
             t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: shading.u,
+            dpdv: shading.v,
+            shading,
             normal_face: NormalFace::FrontOuter,
             material,
         }
@@ -92,41 +94,94 @@ impl HitRecord {
             NormalFace::FrontOuter => outward_normal,
             NormalFace::BackInner => outward_normal.neg(),
         };
+        self.shading = Onb::from_wu(&self.normal, &self.dpdu);
     }
-}
 
-// impl Default for HitRecord {
-//     fn default() -> Self {
-//         Self {
-//             point: Vec3::default(),
-//             normal: Vec3::default(),
-//             t: f64::default(),
-//             normal_face: NormalFace::FrontOuter,
-//             material: Rc::default(),
-//         }
-//     }
-// }
+    /// Sets the surface coordinates and their partial derivatives.
+    pub fn set_uv(&mut self, u: f64, v: f64, dpdu: Vec3, dpdv: Vec3) {
+        self.u = u;
+        self.v = v;
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.shading = Onb::from_wu(&self.normal, &dpdu);
+    }
+}
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Bounds of the object over the shutter interval `[time0, time1]`, or `None`
+    /// if the object is unbounded (e.g. an infinite plane).
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// Solid angle density with which `random` picks `direction` from
+    /// `origin`.
+    ///
+    /// Only shapes that can be put in `World::lights` have to implement this
+    /// and `random`; the defaults are for everything else and must never be
+    /// reached, which debug builds check.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        debug_assert!(false, "pdf_value called on a shape that can't be sampled");
+        0.0
+    }
+
+    /// Random direction from `origin` towards a point on the shape. See
+    /// `pdf_value` for which shapes implement it.
+    fn random(&self, _origin: &Vec3, _sampler: &mut dyn Sampler) -> Vec3 {
+        debug_assert!(false, "random called on a shape that can't be sampled");
+        Vec3(1.0, 0.0, 0.0)
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct HitList<T: Hittable + Send + Sync>(pub Vec<Arc<T>>)
-where
-    T: Hittable;
+/// Any shape that can be shared across render threads.
+pub type DynHittable = dyn Hittable + Send + Sync;
+
+/// List of hittable objects. By default it holds any mix of shape types
+/// (including nested lists); `HitList<Sphere>` etc. keep a single concrete type.
+pub struct HitList<T: Hittable + Send + Sync + ?Sized = DynHittable>(pub Vec<Arc<T>>);
+
 impl<T> HitList<T>
 where
-    T: Hittable + Send + Sync,
+    T: Hittable + Send + Sync + ?Sized,
 {
     pub fn new() -> Self {
         Self(Vec::new())
     }
+
+    pub fn push(&mut self, object: Arc<T>) {
+        self.0.push(object);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T> Default for HitList<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for HitList<T>
+where
+    T: Hittable + Send + Sync + ?Sized,
+{
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
 }
 
 impl<T> Hittable for HitList<T>
 where
-    T: Hittable + Send + Sync,
+    T: Hittable + Send + Sync + ?Sized,
 {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut closest = t_max;
@@ -140,18 +195,31 @@ where
             }
         })
     }
-}
 
-fn hit_sphere(center: Vec3, radius: f64, ray: &Ray) -> f64 {
-    let oc = ray.origin - center;
-    let a = ray.direction.len_sq();
-    let half_b = Vec3::dot(&oc, &ray.direction);
-    let c = oc.len_sq() - radius * radius;
-    let discriminant = half_b * half_b - a * c;
-
-    if discriminant < 0.0 {
-        -1.0
-    } else {
-        (-half_b - discriminant.sqrt()) / a
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.0.iter().try_fold(None, |acc: Option<Aabb>, curr| {
+            let bbox = curr.bounding_box(time0, time1)?;
+            Some(Some(match acc {
+                Some(acc) => Aabb::surrounding(&acc, &bbox),
+                None => bbox,
+            }))
+        })?
+    }
+
+    /// Density of the equal-weight mixture of every object's distribution.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.0.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.0.iter().map(|o| o.pdf_value(origin, direction)).sum();
+        sum / self.0.len() as f64
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        if self.0.is_empty() {
+            return Vec3(1.0, 0.0, 0.0);
+        }
+        let index = sampler.next_index(self.0.len());
+        self.0[index].random(origin, sampler)
     }
 }
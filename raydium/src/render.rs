@@ -1,107 +1,463 @@
 use std::{
-    borrow::BorrowMut,
-    cell::{Ref, RefCell, RefMut},
-    rc::Rc,
-    time::Instant,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use image::{DynamicImage, ImageBuffer, Rgb, RgbImage, Rgba};
-use rand::{Rng, SeedableRng};
-use rayon::prelude::{IntoParallelIterator, ParallelBridge, ParallelIterator};
+use image::{ImageBuffer, Rgba};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSliceMut};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    math::{clamp, IOResult, RectSize},
-    ray::{HitList, Hittable},
-    vec::Vec3,
-    world::{Camera, CameraInfo},
+    film::{Film, FilmOrigin, FilmPixel},
+    integrator::{Integrator, PathDepth, PathTracer},
+    math::RectSize,
+    world::{rays_traced, Camera, World},
 };
 
 pub mod defaults {
     pub const NUM_SAMPLES: u32 = 10;
     pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
     pub const MAX_SCATTER_DEPTH: u32 = 50;
+    pub const ROULETTE_DEPTH: u32 = 3;
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct RayRenderer {
     camera: Camera,
+    integrator: Arc<dyn Integrator>,
+    adaptive: Option<AdaptiveSampling>,
+    cancel: CancelToken,
+}
+
+impl Default for RayRenderer {
+    fn default() -> Self {
+        Self::new(Camera::default())
+    }
 }
 
 impl RayRenderer {
-    pub const fn new(camera: Camera) -> Self {
-        Self { camera }
+    /// Renders through `camera` with the MIS path tracer.
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            integrator: Arc::new(PathTracer),
+            adaptive: None,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    pub fn with_integrator(mut self, integrator: Arc<dyn Integrator>) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn with_adaptive_sampling(mut self, adaptive: Option<AdaptiveSampling>) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Has renders stop early, between image rows, once `cancel` is
+    /// cancelled.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     // TODO :: Put this in World with the Drawable trait
-    pub fn render_world_to_image<T: Hittable + Send + Sync>(
+    pub fn render_world_to_image(
+        &self,
+        world: &World,
+        size: RectSize,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.render_world_to_image_with_progress(world, size, &|_| {})
+    }
+
+    /// Like `render_world_to_image`, calling `progress` as image rows
+    /// finish.
+    pub fn render_world_to_image_with_progress(
         &self,
-        world: &HitList<T>,
+        world: &World,
         size: RectSize,
+        progress: &(dyn Fn(&RenderProgress) + Sync),
     ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        let RectSize { width, height } = size;
-
-        let mut image = DynamicImage::new_rgba8(width, height);
-        let buffer = image
-            .as_mut_rgba8()
-            .expect("Failed to convert image to rgb8");
-
-        {
-            let start = Instant::now();
-            println!("Start render");
-            draw_frame_parallel(buffer, &self.camera, &world, size);
-            println!("End render: Elapsed: {:.2?}", start.elapsed());
+        let mut film = Film::new(size, FilmOrigin::new(&self.camera, 0));
+        self.render_to_film(world, &mut film, progress);
+        film.to_image()
+    }
+
+    /// Adds passes to `film` until every pixel has its share of samples or
+    /// the render is cancelled. A film that already holds samples, from an
+    /// earlier render with fewer samples per pixel say, picks up where it
+    /// stopped.
+    pub fn render_to_film(
+        &self,
+        world: &World,
+        film: &mut Film,
+        progress: &(dyn Fn(&RenderProgress) + Sync),
+    ) {
+        self.render_to_film_by_pass(world, film, progress, |_| {});
+    }
+
+    /// Like `render_to_film`, calling `after_pass` with the film after
+    /// every pass, for previews.
+    pub fn render_to_film_by_pass(
+        &self,
+        world: &World,
+        film: &mut Film,
+        progress: &(dyn Fn(&RenderProgress) + Sync),
+        mut after_pass: impl FnMut(&Film),
+    ) {
+        let run = RenderRun::new(film, progress);
+        log::info!("Start render");
+        while self.pass(world, film, &run) {
+            after_pass(film);
         }
+        log::info!("End render: Elapsed: {:.2?}", run.start.elapsed());
+    }
 
-        image
-            .flipv()
-            .as_rgba8()
-            .expect("Failed to convert image to rgb8")
-            .clone()
+    /// Adds one pass of samples to `film`, returning false if there was
+    /// nothing left to add or the render was cancelled. Without adaptive
+    /// sampling the first pass takes one sample per pixel and each later
+    /// one doubles the count, so early passes make quick previews; with it,
+    /// the pixels still noisy take another batch. The image comes out the
+    /// same however it was split into passes. Progress is reported as if
+    /// the render started with this pass.
+    pub fn render_pass(
+        &self,
+        world: &World,
+        film: &mut Film,
+        progress: &(dyn Fn(&RenderProgress) + Sync),
+    ) -> bool {
+        self.pass(world, film, &RenderRun::new(film, progress))
+    }
+
+    fn pass(&self, world: &World, film: &mut Film, run: &RenderRun) -> bool {
+        if self.cancel.is_cancelled() {
+            return false;
+        }
+        let samples_per_pixel = self.camera.samples_per_pixel();
+        let budget = film.pixels().len() as u64 * samples_per_pixel as u64;
+        let schedule: Box<dyn Fn(&FilmPixel) -> u32 + Sync> = match self.adaptive {
+            None => Box::new(move |pixel| {
+                let n = pixel.samples();
+                samples_per_pixel.saturating_sub(n).min(n.max(1))
+            }),
+            Some(adaptive) => {
+                let max = adaptive
+                    .max_samples
+                    .unwrap_or(samples_per_pixel.saturating_mul(4));
+                let max = max.max(adaptive.min_samples);
+                let noisy = move |pixel: &FilmPixel| {
+                    pixel.samples() >= adaptive.min_samples
+                        && pixel.samples() < max
+                        && pixel.relative_error() >= adaptive.threshold
+                };
+                // Share what's left of the budget between the noisy pixels.
+                let active = film.pixels().iter().filter(|p| noisy(p)).count() as u64;
+                let remaining = budget.saturating_sub(film.total_samples());
+                let share = (remaining / active.max(1)).min(adaptive.min_samples as u64) as u32;
+                Box::new(move |pixel| match pixel.samples() {
+                    n if n < adaptive.min_samples => adaptive.min_samples - n,
+                    n if noisy(pixel) => share.min(max - n),
+                    _ => 0,
+                })
+            }
+        };
+        if film.pixels().iter().all(|p| schedule(p) == 0) {
+            return false;
+        }
+
+        let RectSize { width, height } = film.size();
+        let depth = PathDepth {
+            max: self.camera.max_scatter_depth(),
+            roulette: self.camera.roulette_depth(),
+        };
+        let pass = film.passes() + 1;
+        // Later passes draw with the sampler the film was started with.
+        let origin = *film.origin();
+        let samples = AtomicU64::new(film.total_samples());
+        let rows_done = AtomicU32::new(0);
+        film.pixels_mut()
+            .par_chunks_mut(width as usize)
+            .enumerate()
+            .for_each(|(row, pixels)| {
+                if self.cancel.is_cancelled() {
+                    return;
+                }
+                // Rows are traced bottom up, films are stored top down.
+                let y = height - 1 - row as u32;
+                let rays = rays_traced();
+                let mut taken = 0;
+                for (x, pixel) in (0..width).zip(pixels) {
+                    let count = schedule(pixel);
+                    let first = pixel.samples();
+                    let mut sampler = origin.sampler.build(origin.seed, origin.samples_per_pixel);
+                    for sample in first..first + count {
+                        sampler.start_pixel_sample(x, y, sample);
+                        let (du, dv) = sampler.next_2d();
+                        let u = (x as f64 + du) / width as f64;
+                        let v = (y as f64 + dv) / height as f64;
+
+                        let ray = self.camera.cast_ray(u, v, sampler.as_mut());
+                        pixel.add(
+                            self.integrator
+                                .radiance(&ray, world, depth, sampler.as_mut()),
+                        );
+                    }
+                    taken += count as u64;
+                }
+
+                run.rays.fetch_add(rays_traced() - rays, Ordering::Relaxed);
+                let samples = samples.fetch_add(taken, Ordering::Relaxed) + taken;
+                let elapsed = run.start.elapsed();
+                // Extrapolate from the samples this run has taken so far.
+                let eta = (samples > run.first_samples).then(|| {
+                    let left = budget.saturating_sub(samples) as f64;
+                    elapsed.mul_f64(left / (samples - run.first_samples) as f64)
+                });
+                (run.progress)(&RenderProgress {
+                    pass,
+                    rows_done: rows_done.fetch_add(1, Ordering::Relaxed) + 1,
+                    rows: height,
+                    samples,
+                    budget,
+                    rays: run.rays.load(Ordering::Relaxed),
+                    elapsed,
+                    eta,
+                });
+            });
+
+        if self.cancel.is_cancelled() {
+            return false;
+        }
+        film.count_pass();
+        true
     }
 
     pub const fn camera(&self) -> &Camera {
         &self.camera
     }
+
+    pub fn integrator(&self) -> &dyn Integrator {
+        self.integrator.as_ref()
+    }
 }
 
-fn draw_frame_parallel<T: Hittable + Sync + Send>(
-    buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    camera: &Camera,
-    world: &HitList<T>,
-    size: RectSize,
-) {
-    let RectSize { width, height } = size;
-    let num_samples = camera.samples_per_pixel();
-    buffer
-        .enumerate_pixels_mut()
-        .par_bridge()
-        .for_each(|(x, y, pixel)| {
-            let mut rng = rand::thread_rng();
-            let color = {
-                let mut color = Vec3::zero();
-
-                let scatter_depth = camera.max_scatter_depth();
-                for _ in 0..num_samples - 1 {
-                    let u = (x as f64 + rng.gen::<f64>()) / (width - 1) as f64;
-                    let v = (y as f64 + rng.gen::<f64>()) / (height - 1) as f64;
-
-                    let ray = camera.cast_ray(u, v);
-                    color = color + ray.color(world.clone(), scatter_depth);
-                }
-                color
-            };
+/// Where a render has got to, handed to progress callbacks as image rows
+/// finish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderProgress {
+    /// Pass of the film being rendered, counting from 1.
+    pub pass: u32,
+    /// Image rows finished in this pass, out of `rows`.
+    pub rows_done: u32,
+    pub rows: u32,
+    /// Camera samples the film holds, out of the `budget` the render
+    /// aims for.
+    pub samples: u64,
+    pub budget: u64,
+    /// Rays traced in this render, bounces and shadow rays included.
+    pub rays: u64,
+    pub elapsed: Duration,
+    /// Estimated time left, once there is anything to estimate from.
+    /// Adaptive renders usually finish sooner.
+    pub eta: Option<Duration>,
+}
+
+impl RenderProgress {
+    /// Fraction of the sample budget taken, in [0, 1].
+    pub fn fraction(&self) -> f32 {
+        (self.samples as f64 / self.budget.max(1) as f64).min(1.0) as f32
+    }
+}
+
+/// Stops a render from another thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Bookkeeping shared by the passes of one render.
+struct RenderRun<'a> {
+    start: Instant,
+    /// Samples the film held when the render started.
+    first_samples: u64,
+    rays: AtomicU64,
+    progress: &'a (dyn Fn(&RenderProgress) + Sync),
+}
+
+impl<'a> RenderRun<'a> {
+    fn new(film: &Film, progress: &'a (dyn Fn(&RenderProgress) + Sync)) -> Self {
+        Self {
+            start: Instant::now(),
+            first_samples: film.total_samples(),
+            rays: AtomicU64::new(0),
+            progress,
+        }
+    }
+}
+
+/// Adaptive sampling settings (`[render.adaptive]`). Pixels stop taking
+/// samples once the standard error of their mean luminance, relative to the
+/// mean, falls below `threshold`, and the samples they didn't need go to the
+/// pixels that are still noisy. `samples_per_pixel` stays the average
+/// budget.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveSampling {
+    pub threshold: f64,
+    /// Samples every pixel takes before its error is trusted, and the size
+    /// of each later batch.
+    pub min_samples: u32,
+    /// Most samples one pixel may take, four times `samples_per_pixel` if
+    /// unset.
+    pub max_samples: Option<u32>,
+}
 
-            write_color(pixel, &color, num_samples);
-        });
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            threshold: 0.01,
+            min_samples: 16,
+            max_samples: None,
+        }
+    }
 }
 
-fn write_color(pixel: &mut Rgba<u8>, color: &Vec3, samples_per_pixel: u32) {
-    let scale = 1.0 / samples_per_pixel as f64;
-    let r = (color.x() * scale).sqrt();
-    let g = (color.y() * scale).sqrt();
-    let b = (color.z() * scale).sqrt();
-    pixel[0] = (255.0 * clamp(r, 0.0, 0.999)) as u8;
-    pixel[1] = (255.0 * clamp(g, 0.0, 0.999)) as u8;
-    pixel[2] = (255.0 * clamp(b, 0.0, 0.999)) as u8;
-    pixel[3] = 255;
+impl AdaptiveSampling {
+    /// Checks the settings against the `samples_per_pixel` budget they
+    /// share out.
+    pub fn validate(&self, samples_per_pixel: u32) -> Result<(), String> {
+        if !(self.threshold.is_finite() && self.threshold > 0.0) {
+            return Err("adaptive threshold must be positive".into());
+        }
+        if self.min_samples < 2 {
+            return Err("adaptive min_samples must be at least 2".into());
+        }
+        if self.min_samples > samples_per_pixel {
+            return Err(format!(
+                "adaptive min_samples ({}) must not exceed samples_per_pixel ({})",
+                self.min_samples, samples_per_pixel
+            ));
+        }
+        if self.max_samples.is_some_and(|max| max < self.min_samples) {
+            return Err("adaptive max_samples must be at least min_samples".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::scene::SceneDesc;
+
+    const SCENE: &str = r#"
+[render]
+width = 24
+height = 16
+samples_per_pixel = 8
+seed = 7
+
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.glass]
+type = "dielectric"
+ir = 1.5
+
+[[objects]]
+type = "sphere"
+center = [0.0, -100.5, -1.0]
+radius = 100.0
+material = "ground"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = "glass"
+"#;
+
+    /// Renders `src` on a pool of `threads` threads.
+    fn render(src: &str, threads: usize) -> Vec<u8> {
+        let scene = SceneDesc::parse(src).unwrap().build(Path::new("")).unwrap();
+        let renderer = RayRenderer::new(Camera::with_info(&scene.camera))
+            .with_integrator(scene.integrator.build())
+            .with_adaptive_sampling(scene.adaptive);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| renderer.render_world_to_image(&scene.world, scene.size))
+            .into_raw()
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_on_any_thread_count() {
+        assert_eq!(render(SCENE, 1), render(SCENE, 4));
+
+        let adaptive = format!(
+            "{}\n[render.adaptive]\nthreshold = 0.05\nmin_samples = 4\n",
+            SCENE
+        );
+        assert_eq!(render(&adaptive, 1), render(&adaptive, 4));
+    }
+
+    #[test]
+    fn different_seed_renders_differently() {
+        let reseeded = SCENE.replace("seed = 7", "seed = 8");
+        assert_ne!(render(SCENE, 1), render(&reseeded, 1));
+    }
+
+    /// Renders `src` into `film`, or a new film if there is none.
+    fn render_film(src: &str, film: Option<Film>) -> Film {
+        let scene = SceneDesc::parse(src).unwrap().build(Path::new("")).unwrap();
+        let renderer = RayRenderer::new(Camera::with_info(&scene.camera))
+            .with_integrator(scene.integrator.build());
+        let mut film =
+            film.unwrap_or_else(|| Film::new(scene.size, FilmOrigin::new(renderer.camera(), 0)));
+        renderer.render_to_film(&scene.world, &mut film, &|_| {});
+        film
+    }
+
+    #[test]
+    fn resumed_render_matches_one_taken_in_one_go() {
+        for sampler in ["independent", "sobol"] {
+            let src = SCENE.replace("seed = 7", &format!("seed = 7\nsampler = \"{sampler}\""));
+            let four = src.replace("samples_per_pixel = 8", "samples_per_pixel = 4");
+
+            let path = std::env::temp_dir().join(format!(
+                "raydium-resume-{}-{}",
+                std::process::id(),
+                sampler
+            ));
+            render_film(&four, None).save(&path).unwrap();
+            let loaded = Film::load(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let resumed = render_film(&src, Some(loaded.unwrap()));
+            let fresh = render_film(&src, None);
+            assert_eq!(resumed.total_samples(), fresh.total_samples(), "{sampler}");
+            assert_eq!(resumed.pixels(), fresh.pixels(), "{sampler}");
+        }
+    }
 }
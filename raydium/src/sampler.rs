@@ -0,0 +1,723 @@
+use std::{f64::consts::PI, str::FromStr, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+/// Source of the uniform numbers a render consumes, organised as a sequence
+/// of dimensions per pixel sample: the n-th number drawn for a sample comes
+/// from dimension n. Low-discrepancy samplers spread the values of each
+/// dimension evenly over a pixel's samples, so images converge faster than
+/// with independent randoms.
+///
+/// Every implementation derives its values from the render seed, the pixel
+/// and the sample index alone, so the same seed reproduces the same image
+/// however the work is split between threads.
+pub trait Sampler {
+    /// Moves to sample `index` of pixel (`x`, `y`), back at the first
+    /// dimension.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+
+    /// Uniform in [0, 1) from the next dimension.
+    fn next_1d(&mut self) -> f64;
+
+    /// Uniform point in [0, 1)² from the next pair of dimensions.
+    fn next_2d(&mut self) -> (f64, f64);
+
+    /// Uniform in [0, `n`), for picking one of `n` things.
+    fn next_index(&mut self, n: usize) -> usize {
+        ((self.next_1d() * n as f64) as usize).min(n.saturating_sub(1))
+    }
+}
+
+/// Which `Sampler` a render uses (`sampler` under `[render]`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// The name this is written as in scene files.
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue_noise",
+        }
+    }
+
+    /// A sampler for a render with `seed` taking `samples_per_pixel`
+    /// samples in each pixel.
+    pub fn build(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| anyhow::anyhow!("unknown sampler `{}`", s))
+    }
+}
+
+/// Independent uniform randoms (SplitMix64), reseeded for every pixel
+/// sample.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    state: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: mix(seed),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // SplitMix64: a Weyl sequence scrambled by the finalizer.
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        let pixel = (y as u64) << 32 | x as u64;
+        self.state = mix(mix(mix(self.seed ^ mix(pixel)) ^ index as u64));
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        to_unit(self.next_u64())
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// Jittered stratification: each dimension is split into one stratum per
+/// sample (a near-square grid of them for 2D), and every sample of a pixel
+/// lands at a random point of a different stratum. Dimensions are
+/// decorrelated by shuffling which sample gets which stratum.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples: u32,
+    x_strata: u32,
+    y_strata: u32,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        let samples = samples_per_pixel.max(1);
+        let x_strata = (samples as f64).sqrt().ceil() as u32;
+        let y_strata = samples.div_ceil(x_strata);
+        Self {
+            seed,
+            samples,
+            x_strata,
+            y_strata,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the current pixel and dimension, then moves to the next one.
+    fn next_dimension(&mut self) -> u64 {
+        let h = hash(&[self.pixel, self.dimension]);
+        self.dimension += 1;
+        h
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let h = self.next_dimension();
+        let stratum = permutation_element(self.index % self.samples, self.samples, h as u32);
+        let jitter = to_unit(hash(&[h, self.index as u64]));
+        (stratum as f64 + jitter) / self.samples as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let h = self.next_dimension();
+        let count = self.x_strata * self.y_strata;
+        let stratum = permutation_element(self.index % count, count, h as u32);
+        let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+        let jitter = hash(&[h, self.index as u64]);
+        let (jx, jy) = (to_unit(jitter), to_unit(mix(jitter)));
+        (
+            (sx as f64 + jx) / self.x_strata as f64,
+            (sy as f64 + jy) / self.y_strata as f64,
+        )
+    }
+}
+
+/// Halton sequence: dimension n is the radical inverse of the sample index
+/// in the n-th prime base, with its digits randomly permuted (Owen
+/// scrambling) per pixel and dimension. Scrambling also breaks up the
+/// correlation between the large bases of neighbouring dimensions that
+/// shows as streaks with plain Halton points. Dimensions beyond the prime
+/// table fall back to independent randoms.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    samples: u32,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        Self {
+            seed,
+            samples: samples_per_pixel,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let h = hash(&[self.pixel, dimension as u64]);
+        match PRIMES.get(dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.index as u64, self.samples, h),
+            None => to_unit(hash(&[h, self.index as u64])),
+        }
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// Owen-scrambled Sobol points: every pair of dimensions draws from the
+/// first two Sobol dimensions, with the sample order shuffled and the bits
+/// scrambled per pixel and pair ("padded" Sobol, Burley 2020). Converges
+/// fastest when samples per pixel is a power of two.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Scrambling seed for the current dimension, then moves past it.
+    fn next_dimension(&mut self) -> u64 {
+        let h = hash(&[self.pixel, self.dimension]);
+        self.dimension += 1;
+        h
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let h = self.next_dimension();
+        let index = owen_scramble(self.index, h as u32);
+        u32_to_unit(owen_scramble(sobol_0(index), (h >> 32) as u32))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let h = self.next_dimension();
+        let index = owen_scramble(self.index, h as u32);
+        let h2 = mix(h);
+        (
+            u32_to_unit(owen_scramble(sobol_0(index), h2 as u32)),
+            u32_to_unit(owen_scramble(sobol_1(index), (h2 >> 32) as u32)),
+        )
+    }
+}
+
+/// Each pixel takes the same Sobol points, shifted (modulo 1) by the value
+/// of a blue-noise mask at the pixel (Georgiev and Fajardo, "Blue-noise
+/// Dithered Sampling", 2016). Neighbouring pixels get very different shifts,
+/// which pushes the remaining error into high frequencies, where it reads as
+/// fine grain rather than blotches. Each dimension reads the mask at its own
+/// offset and shuffles the sample order to stay uncorrelated with the rest.
+#[derive(Debug, Clone)]
+pub struct BlueNoiseSampler {
+    seed: u64,
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Shuffling seed for the current dimension, then moves past it. It
+    /// doesn't depend on the pixel, which keeps the shifts the only
+    /// difference between pixels.
+    fn next_dimension(&mut self) -> u64 {
+        let h = hash(&[self.seed, self.dimension]);
+        self.dimension += 1;
+        h
+    }
+
+    /// The mask at this pixel, read at an offset picked by `h`.
+    fn shift(&self, h: u64) -> f64 {
+        let size = BLUE_NOISE_SIZE as u64;
+        let x = (self.x as u64 + h % size) % size;
+        let y = (self.y as u64 + (h >> 32) % size) % size;
+        blue_noise_mask()[(y * size + x) as usize] as f64
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let h = self.next_dimension();
+        let index = owen_scramble(self.index, h as u32);
+        wrap(u32_to_unit(sobol_0(index)) + self.shift(mix(h)))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let h = self.next_dimension();
+        let index = owen_scramble(self.index, h as u32);
+        let h2 = mix(h);
+        (
+            wrap(u32_to_unit(sobol_0(index)) + self.shift(h2)),
+            wrap(u32_to_unit(sobol_1(index)) + self.shift(mix(h2))),
+        )
+    }
+}
+
+/// Maps a uniform point in [0, 1)² to a uniform point on the unit disk
+/// (Shirley and Chiu's concentric mapping, which keeps strata compact).
+pub fn sample_disk((u0, u1): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2.0 * u0 - 1.0, 2.0 * u1 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Largest f64 below one, so mapped samples stay in [0, 1).
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+fn u32_to_unit(bits: u32) -> f64 {
+    (bits as f64 / (1u64 << 32) as f64).min(ONE_MINUS_EPSILON)
+}
+
+fn wrap(value: f64) -> f64 {
+    let value = value - value.floor();
+    value.min(ONE_MINUS_EPSILON)
+}
+
+/// SplitMix64 finalizer: scrambles `z` so that nearby inputs give unrelated
+/// outputs.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x6a09e667f3bcc909, |h, &v| mix(h ^ mix(v)))
+}
+
+/// Element `i` of a random permutation of [0, `n`) picked by `seed`,
+/// without storing it (Kensler, "Correlated Multi-Jittered Sampling", 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+/// `index` written in `base` with its digits mirrored about the point, each
+/// digit permuted by a permutation that depends on `seed` and the digits
+/// before it (Owen scrambling). Digits past both `index` and the `samples`
+/// indices in use would all be permuted zeros, which is the same as a
+/// uniform jitter within the last digit, so that's what they become.
+fn scrambled_radical_inverse(base: u64, mut index: u64, samples: u32, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut reversed = 0u64;
+    let mut inv_base_n = 1.0;
+    let mut reach = samples.saturating_sub(1) as u64;
+    while index > 0 || reach > 0 {
+        let next = index / base;
+        let digit = (index - next * base) as u32;
+        let permuted = permutation_element(digit, base as u32, mix(seed ^ reversed) as u32);
+        reversed = reversed * base + permuted as u64;
+        inv_base_n *= inv_base;
+        index = next;
+        reach /= base;
+    }
+    let jitter = to_unit(mix(seed ^ reversed ^ 0x9e3779b97f4a7c15));
+    ((reversed as f64 + jitter) * inv_base_n).min(ONE_MINUS_EPSILON)
+}
+
+const PRIMES: [u64; 128] = primes();
+
+const fn primes<const N: usize>() -> [u64; N] {
+    let mut primes = [0; N];
+    let mut count = 0;
+    let mut candidate = 2;
+    while count < N {
+        let mut i = 0;
+        let mut prime = true;
+        while i < count && primes[i] * primes[i] <= candidate {
+            if candidate % primes[i] == 0 {
+                prime = false;
+                break;
+            }
+            i += 1;
+        }
+        if prime {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+/// First Sobol dimension: the van der Corput sequence.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second Sobol dimension.
+fn sobol_1(index: u32) -> u32 {
+    let mut result = 0;
+    let mut bits = index;
+    let mut i = 0;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            result ^= SOBOL_1_DIRECTIONS[i];
+        }
+        bits >>= 1;
+        i += 1;
+    }
+    result
+}
+
+/// Direction numbers of the second Sobol dimension (primitive polynomial
+/// x + 1): m_k = 2 m_(k-1) xor m_(k-1), left-aligned.
+const SOBOL_1_DIRECTIONS: [u32; 32] = {
+    let mut directions = [0; 32];
+    let mut m: u64 = 1;
+    let mut k = 0;
+    while k < 32 {
+        directions[k] = (m << (31 - k)) as u32;
+        m = (m << 1) ^ m;
+        k += 1;
+    }
+    directions
+};
+
+/// Nested uniform (Owen) scrambling of the bits of `x`, in the hash-based
+/// form of Burley, "Practical Hash-based Owen Scrambling", 2020.
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+/// Side of the tiled blue-noise mask.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Blue-noise threshold mask with values in [0, 1), generated once.
+fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 0x5eed))
+}
+
+/// Ulichney's void-and-cluster method: ranks every cell of a toroidal
+/// `size`² grid so that the cells below any threshold are spread out as
+/// evenly as possible.
+fn void_and_cluster(size: usize, seed: u64) -> Vec<f32> {
+    let n = size * size;
+    let sigma = 1.5;
+    let kernel: Vec<f64> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f64;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            f64::exp(-(dx * dx + dy * dy) / (2.0 * sigma * sigma))
+        })
+        .collect();
+
+    let mut on = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let splat = |energy: &mut [f64], cell: usize, sign: f64| {
+        let (cx, cy) = (cell % size, cell / size);
+        for y in 0..size {
+            let dy = (y + size - cy) % size;
+            for x in 0..size {
+                let dx = (x + size - cx) % size;
+                energy[y * size + x] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+    let tightest_cluster = |on: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&i| on[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+    let largest_void = |on: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&i| !on[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+
+    // Random initial pattern, then relaxed by moving the tightest cluster
+    // into the largest void until that stops changing anything.
+    let initial = n / 10;
+    let mut rng = IndependentSampler::new(seed);
+    let mut placed = 0;
+    while placed < initial {
+        let cell = rng.next_index(n);
+        if !on[cell] {
+            on[cell] = true;
+            splat(&mut energy, cell, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&on, &energy).expect("pattern is not empty");
+        on[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&on, &energy).expect("pattern is not full");
+        on[cluster] = true;
+        splat(&mut energy, cluster, 1.0);
+        if void == cluster {
+            break;
+        }
+        on[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        on[void] = true;
+        splat(&mut energy, void, 1.0);
+    }
+
+    // Rank the initial points by removing clusters, then fill voids.
+    let mut rank = vec![0usize; n];
+    let (mut removed_on, mut removed_energy) = (on.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&removed_on, &removed_energy).expect("pattern is not empty");
+        removed_on[cluster] = false;
+        splat(&mut removed_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    for r in initial..n {
+        let void = largest_void(&on, &energy).expect("pattern is not full");
+        on[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| ((r as f64 + 0.5) / n as f64) as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+
+    /// The first `count` samples of pixel (`x`, `y`), each reduced to its
+    /// first 1D and first 2D dimension.
+    fn first_dimensions(
+        sampler: &mut dyn Sampler,
+        x: u32,
+        y: u32,
+        count: u32,
+    ) -> Vec<(f64, (f64, f64))> {
+        (0..count)
+            .map(|index| {
+                sampler.start_pixel_sample(x, y, index);
+                (sampler.next_1d(), sampler.next_2d())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_sampler_stays_in_the_unit_interval() {
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.build(SEED, 16);
+            for (x, y) in [(0, 0), (7, 3), (1023, 511)] {
+                for index in 0..64 {
+                    sampler.start_pixel_sample(x, y, index);
+                    for _ in 0..200 {
+                        let a = sampler.next_1d();
+                        let (b, c) = sampler.next_2d();
+                        for v in [a, b, c] {
+                            assert!((0.0..1.0).contains(&v), "{}: {v}", kind.name());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_and_sobol_put_one_sample_in_each_stratum() {
+        const SAMPLES: u32 = 16;
+        const SIDE: usize = 4;
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.build(SEED, SAMPLES);
+            for (x, y) in [(0, 0), (5, 9)] {
+                let mut strata_1d = [0; SAMPLES as usize];
+                let mut strata_2d = [[0; SIDE]; SIDE];
+                for (a, (b, c)) in first_dimensions(&mut *sampler, x, y, SAMPLES) {
+                    strata_1d[(a * SAMPLES as f64) as usize] += 1;
+                    strata_2d[(c * SIDE as f64) as usize][(b * SIDE as f64) as usize] += 1;
+                }
+                assert_eq!(strata_1d, [1; SAMPLES as usize], "{}", kind.name());
+                assert_eq!(strata_2d, [[1; SIDE]; SIDE], "{}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn samples_depend_only_on_seed_pixel_and_index() {
+        for kind in SamplerKind::ALL {
+            let mut a = kind.build(SEED, 16);
+            let mut b = kind.build(SEED, 16);
+            // Visiting other pixels first must not change anything.
+            first_dimensions(&mut *b, 3, 1, 5);
+            assert_eq!(
+                first_dimensions(&mut *a, 2, 6, 16),
+                first_dimensions(&mut *b, 2, 6, 16),
+                "{}",
+                kind.name()
+            );
+
+            let mut reseeded = kind.build(SEED + 1, 16);
+            assert_ne!(
+                first_dimensions(&mut *a, 2, 6, 16),
+                first_dimensions(&mut *reseeded, 2, 6, 16),
+                "{}",
+                kind.name()
+            );
+            assert_ne!(
+                first_dimensions(&mut *a, 2, 6, 16),
+                first_dimensions(&mut *a, 6, 2, 16),
+                "{}",
+                kind.name()
+            );
+        }
+    }
+}
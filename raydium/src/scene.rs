@@ -0,0 +1,1193 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer, Serialize,
+};
+use toml::Spanned;
+
+use crate::{
+    bvh::Bvh,
+    environment::Background,
+    geom::{MovingSphere, Quad, Sphere, Triangle},
+    instance::Instance,
+    integrator::IntegratorDesc,
+    material::{
+        Conductor, Dielectric, DiffuseLight, Fresnel, Lambertian, Material, Metal, RoughDielectric,
+    },
+    math::RectSize,
+    microfacet::{ComplexIor, Ggx},
+    obj::load_obj,
+    principled::{constant, GltfMetallicRoughness, Principled},
+    ray::{DynHittable, HitList},
+    render::{defaults, AdaptiveSampling},
+    sampler::SamplerKind,
+    texture::{
+        Channel, ChannelTexture, CheckerTexture, ImageTexture, NoiseStyle, NoiseTexture,
+        SolidColor, Texture,
+    },
+    transform::{Quat, Transform},
+    vec::Vec3,
+    world::{CameraInfo, World},
+};
+
+/// Material entry of a scene file, keyed by name under `[materials]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: ColorDesc,
+    },
+    Metal {
+        albedo: ColorDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ir: f64,
+        /// Filter applied to light refracting through the surface.
+        #[serde(default = "default_tint")]
+        tint: ColorDesc,
+        /// Beer–Lambert absorption coefficients per unit distance inside.
+        #[serde(default)]
+        absorption: Vec3,
+        #[serde(default)]
+        fresnel: Fresnel,
+        /// GGX roughness; above zero the glass is frosted.
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64,
+    },
+    /// GGX microfacet metal with either a `preset` or an explicit complex
+    /// index of refraction `eta + i k`.
+    Conductor {
+        #[serde(default)]
+        preset: Option<ConductorPreset>,
+        #[serde(default)]
+        eta: Option<Vec3>,
+        #[serde(default)]
+        k: Option<Vec3>,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64,
+    },
+    /// Disney-style uber material.
+    Principled(Box<PrincipledDesc>),
+    DiffuseLight {
+        emit: ColorDesc,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+impl ConductorPreset {
+    pub fn ior(self) -> ComplexIor {
+        match self {
+            ConductorPreset::Gold => ComplexIor::GOLD,
+            ConductorPreset::Copper => ComplexIor::COPPER,
+            ConductorPreset::Aluminium => ComplexIor::ALUMINIUM,
+        }
+    }
+}
+
+/// A colour parameter: either a plain `[r, g, b]` or an inline texture table.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ColorDesc {
+    Color(Vec3),
+    Texture(TextureDesc),
+}
+
+// Hand-written rather than `#[serde(untagged)]` so that mistakes inside a
+// texture table report the actual problem instead of "did not match any variant".
+impl<'de> Deserialize<'de> for ColorDesc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = ColorDesc;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an [r, g, b] colour or a texture table")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<ColorDesc, A::Error> {
+                Vec3::deserialize(SeqAccessDeserializer::new(seq)).map(ColorDesc::Color)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ColorDesc, A::Error> {
+                TextureDesc::deserialize(MapAccessDeserializer::new(map)).map(ColorDesc::Texture)
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+impl From<Vec3> for ColorDesc {
+    fn from(color: Vec3) -> Self {
+        ColorDesc::Color(color)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDesc {
+    /// 3D checkerboard of cubes of side `scale`.
+    Checker {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        even: Box<ColorDesc>,
+        odd: Box<ColorDesc>,
+    },
+    /// Image file, resolved relative to the scene file.
+    Image { path: PathBuf },
+    /// Perlin noise at frequency `scale`, modulating `color`.
+    Noise {
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default)]
+        style: NoiseStyle,
+        #[serde(default = "default_white")]
+        color: Vec3,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+fn default_time1() -> f64 {
+    1.0
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_white() -> Vec3 {
+    Vec3::WHITE
+}
+
+fn default_tint() -> ColorDesc {
+    Vec3::WHITE.into()
+}
+
+fn is_non_negative(c: &Vec3) -> bool {
+    c.x() >= 0.0 && c.y() >= 0.0 && c.z() >= 0.0
+}
+
+impl ColorDesc {
+    /// Describes the first problem found, if any.
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            ColorDesc::Color(c) if !is_non_negative(c) => Err("has a negative component"),
+            ColorDesc::Color(_) => Ok(()),
+            ColorDesc::Texture(texture) => texture.validate(),
+        }
+    }
+
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Arc<dyn Texture>> {
+        match self {
+            ColorDesc::Color(c) => Ok(Arc::new(SolidColor::new(*c))),
+            ColorDesc::Texture(texture) => texture.build(base_dir),
+        }
+    }
+}
+
+impl TextureDesc {
+    /// Describes the first problem found, if any.
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            TextureDesc::Checker { scale, even, odd } => {
+                if !(scale.is_finite() && *scale > 0.0) {
+                    return Err("has a checker scale that is not positive");
+                }
+                even.validate()?;
+                odd.validate()
+            }
+            TextureDesc::Image { .. } => Ok(()),
+            TextureDesc::Noise { scale, color, .. } => {
+                if !(scale.is_finite() && *scale > 0.0) {
+                    return Err("has a noise scale that is not positive");
+                }
+                ColorDesc::Color(*color).validate()
+            }
+        }
+    }
+
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Arc<dyn Texture>> {
+        Ok(match self {
+            TextureDesc::Checker { scale, even, odd } => Arc::new(CheckerTexture::new(
+                *scale,
+                even.build(base_dir)?,
+                odd.build(base_dir)?,
+            )),
+            TextureDesc::Image { path } => Arc::new(ImageTexture::load(&base_dir.join(path))?),
+            TextureDesc::Noise {
+                scale,
+                style,
+                color,
+                seed,
+            } => Arc::new(NoiseTexture::new(*seed, *scale, *style, *color)),
+        })
+    }
+}
+
+/// A scalar parameter in [0, 1]: either a number or a texture table with an
+/// optional `channel` to read (`"r"` by default). Images are read as linear
+/// data, not sRGB colours.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ScalarDesc {
+    Value(f64),
+    Texture(ScalarTextureDesc),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScalarTextureDesc {
+    #[serde(flatten)]
+    pub texture: TextureDesc,
+    #[serde(default)]
+    pub channel: Channel,
+}
+
+// Hand-written for the same reason as `ColorDesc`'s.
+impl<'de> Deserialize<'de> for ScalarDesc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScalarVisitor;
+
+        impl<'de> Visitor<'de> for ScalarVisitor {
+            type Value = ScalarDesc;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number or a texture table")
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<ScalarDesc, E> {
+                Ok(ScalarDesc::Value(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ScalarDesc, E> {
+                Ok(ScalarDesc::Value(v as f64))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ScalarDesc, E> {
+                Ok(ScalarDesc::Value(v as f64))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ScalarDesc, A::Error> {
+                ScalarTextureDesc::deserialize(MapAccessDeserializer::new(map))
+                    .map(ScalarDesc::Texture)
+            }
+        }
+
+        deserializer.deserialize_any(ScalarVisitor)
+    }
+}
+
+impl From<f64> for ScalarDesc {
+    fn from(value: f64) -> Self {
+        ScalarDesc::Value(value)
+    }
+}
+
+impl ScalarDesc {
+    /// Describes the first problem found, if any.
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            ScalarDesc::Value(v) if !(0.0..=1.0).contains(v) => Err("must be in [0, 1]"),
+            ScalarDesc::Value(_) => Ok(()),
+            ScalarDesc::Texture(t) => t.texture.validate(),
+        }
+    }
+
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Arc<dyn Texture>> {
+        match self {
+            ScalarDesc::Value(v) => Ok(constant(*v)),
+            ScalarDesc::Texture(ScalarTextureDesc { texture, channel }) => {
+                let texture: Arc<dyn Texture> = match texture {
+                    TextureDesc::Image { path } => {
+                        Arc::new(ImageTexture::load_data(&base_dir.join(path))?)
+                    }
+                    texture => texture.build(base_dir)?,
+                };
+                Ok(Arc::new(ChannelTexture::new(texture, *channel)))
+            }
+        }
+    }
+}
+
+/// Parameters of a `principled` material; see `Principled` for their meaning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrincipledDesc {
+    pub base_color: ColorDesc,
+    /// Defaults to 0, or 1 with `metallic_roughness` as in glTF.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metallic: Option<ScalarDesc>,
+    /// Defaults to 0.5, or 1 with `metallic_roughness` as in glTF.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<ScalarDesc>,
+    pub specular: ScalarDesc,
+    pub specular_tint: ScalarDesc,
+    pub sheen: ScalarDesc,
+    pub sheen_tint: ScalarDesc,
+    pub clearcoat: ScalarDesc,
+    pub clearcoat_gloss: ScalarDesc,
+    pub transmission: ScalarDesc,
+    pub subsurface: ScalarDesc,
+    pub ior: f64,
+    /// glTF-style packed image with roughness in green and metalness in
+    /// blue, scaled by `roughness` and `metallic`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metallic_roughness: Option<PathBuf>,
+}
+
+impl Default for PrincipledDesc {
+    fn default() -> Self {
+        Self {
+            base_color: Vec3(0.8, 0.8, 0.8).into(),
+            metallic: None,
+            roughness: None,
+            specular: 0.5.into(),
+            specular_tint: 0.0.into(),
+            sheen: 0.0.into(),
+            sheen_tint: 0.5.into(),
+            clearcoat: 0.0.into(),
+            clearcoat_gloss: 1.0.into(),
+            transmission: 0.0.into(),
+            subsurface: 0.0.into(),
+            ior: 1.5,
+            metallic_roughness: None,
+        }
+    }
+}
+
+impl PrincipledDesc {
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Principled> {
+        let base_color = self.base_color.build(base_dir)?;
+        let mut material = match &self.metallic_roughness {
+            Some(path) => {
+                let factor = |s: &Option<ScalarDesc>| match s {
+                    None => Ok(1.0),
+                    Some(ScalarDesc::Value(v)) => Ok(*v),
+                    Some(ScalarDesc::Texture(_)) => Err(anyhow!(
+                        "metallic and roughness must be numbers with metallic_roughness"
+                    )),
+                };
+                Principled::from(GltfMetallicRoughness {
+                    base_color_texture: Some(base_color),
+                    metallic_factor: factor(&self.metallic)?,
+                    roughness_factor: factor(&self.roughness)?,
+                    metallic_roughness_texture: Some(Arc::new(ImageTexture::load_data(
+                        &base_dir.join(path),
+                    )?)),
+                    ..Default::default()
+                })
+            }
+            None => {
+                let or = |s: &Option<ScalarDesc>, default| match s {
+                    Some(s) => s.build(base_dir),
+                    None => Ok(constant(default)),
+                };
+                Principled {
+                    base_color,
+                    metallic: or(&self.metallic, 0.0)?,
+                    roughness: or(&self.roughness, 0.5)?,
+                    ..Default::default()
+                }
+            }
+        };
+        material.specular = self.specular.build(base_dir)?;
+        material.specular_tint = self.specular_tint.build(base_dir)?;
+        material.sheen = self.sheen.build(base_dir)?;
+        material.sheen_tint = self.sheen_tint.build(base_dir)?;
+        material.clearcoat = self.clearcoat.build(base_dir)?;
+        material.clearcoat_gloss = self.clearcoat_gloss.build(base_dir)?;
+        material.transmission = self.transmission.build(base_dir)?;
+        material.subsurface = self.subsurface.build(base_dir)?;
+        material.ior = self.ior;
+        Ok(material)
+    }
+
+    fn scalars(&self) -> impl Iterator<Item = (&'static str, &ScalarDesc)> {
+        [
+            ("metallic", self.metallic.as_ref()),
+            ("roughness", self.roughness.as_ref()),
+            ("specular", Some(&self.specular)),
+            ("specular_tint", Some(&self.specular_tint)),
+            ("sheen", Some(&self.sheen)),
+            ("sheen_tint", Some(&self.sheen_tint)),
+            ("clearcoat", Some(&self.clearcoat)),
+            ("clearcoat_gloss", Some(&self.clearcoat_gloss)),
+            ("transmission", Some(&self.transmission)),
+            ("subsurface", Some(&self.subsurface)),
+        ]
+        .into_iter()
+        .filter_map(|(key, scalar)| Some((key, scalar?)))
+    }
+}
+
+/// Object entry of a scene file (`[[objects]]`). `material` names an entry of
+/// `[materials]`; for OBJ files it is only used for faces without `usemtl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectDesc {
+    Sphere {
+        center: Vec3,
+        radius: f64,
+        material: String,
+    },
+    /// Sphere moving from `center0` at `time0` to `center1` at `time1`;
+    /// blurred when the camera shutter is open for part of that time.
+    MovingSphere {
+        center0: Vec3,
+        center1: Vec3,
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [Vec3; 3],
+        material: String,
+    },
+    /// Parallelogram with corner `q` and edges `u` and `v`.
+    Quad {
+        q: Vec3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    /// Wavefront OBJ model. Every object naming the same file and material
+    /// shares one copy of the mesh, placed by its own `transform`.
+    Obj {
+        path: PathBuf,
+        material: String,
+        #[serde(default, skip_serializing_if = "TransformDesc::is_identity")]
+        transform: TransformDesc,
+    },
+}
+
+/// Placement of an object: scaled, then rotated about x, y and z (degrees, in
+/// that order), then translated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub translate: Vec3,
+    pub rotate: Vec3,
+    pub scale: Vec3,
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        Self {
+            translate: Vec3::zero(),
+            rotate: Vec3::zero(),
+            scale: Vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl TransformDesc {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `None` if the scale is zero or not finite on some axis.
+    pub fn to_transform(&self) -> Option<Transform> {
+        Transform::from_trs(
+            &self.translate,
+            &Quat::from_euler_degrees(&self.rotate),
+            &self.scale,
+        )
+    }
+}
+
+impl ObjectDesc {
+    pub fn material(&self) -> &str {
+        match self {
+            ObjectDesc::Sphere { material, .. } => material,
+            ObjectDesc::MovingSphere { material, .. } => material,
+            ObjectDesc::Triangle { material, .. } => material,
+            ObjectDesc::Quad { material, .. } => material,
+            ObjectDesc::Obj { material, .. } => material,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_scatter_depth: u32,
+    /// Bounces every path takes before Russian roulette may end it.
+    pub roulette_depth: u32,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub integrator: IntegratorDesc,
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: (800.0 / defaults::ASPECT_RATIO) as u32,
+            samples_per_pixel: defaults::NUM_SAMPLES,
+            max_scatter_depth: defaults::MAX_SCATTER_DEPTH,
+            roulette_depth: defaults::ROULETTE_DEPTH,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            integrator: IntegratorDesc::default(),
+            adaptive: None,
+        }
+    }
+}
+
+/// Declarative description of a scene, as read from or written to a TOML
+/// scene file. Lights are ordinary objects with a `diffuse_light` material.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SceneDesc {
+    #[serde(default)]
+    pub camera: CameraInfo,
+    #[serde(default)]
+    pub render: RenderSettings,
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+}
+
+/// A scene ready to hand to `RayRenderer`.
+pub struct Scene {
+    pub camera: CameraInfo,
+    pub size: RectSize,
+    pub world: World,
+    pub integrator: IntegratorDesc,
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+/// Source locations of each key of a table.
+type TableSpans = BTreeMap<String, Spanned<toml::Value>>;
+
+/// Mirrors the parts of a scene file we need source locations for.
+#[derive(Deserialize)]
+struct SceneSpans {
+    #[serde(default)]
+    camera: TableSpans,
+    #[serde(default)]
+    render: TableSpans,
+    #[serde(default)]
+    background: TableSpans,
+    #[serde(default)]
+    materials: BTreeMap<String, TableSpans>,
+    #[serde(default)]
+    objects: Vec<TableSpans>,
+}
+
+fn key_span(table: Option<&TableSpans>, key: &str) -> Option<Range<usize>> {
+    table?.get(key).map(|v| v.span())
+}
+
+impl SceneDesc {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let src = fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene file {}", path.display()))?;
+        Self::parse(&src).map_err(|e| anyhow!("{}:{:#}", path.display(), e))
+    }
+
+    /// Parses and validates scene source. Errors are prefixed with the
+    /// 1-based line they refer to.
+    pub fn parse(src: &str) -> anyhow::Result<Self> {
+        let desc: SceneDesc = toml::from_str(src).map_err(|e| toml_error(src, &e))?;
+        let spans: SceneSpans = toml::from_str(src).map_err(|e| toml_error(src, &e))?;
+        desc.validate(src, &spans)?;
+        Ok(desc)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, self.to_toml()?)
+            .with_context(|| format!("failed to write scene file {}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Hash of everything that decides what the scene looks like, telling
+    /// films of different scenes apart. Sample counts, seed and sampler are
+    /// left out, since films record the ones that matter themselves; files
+    /// the scene loads, like OBJ models, count by path only.
+    pub fn film_hash(&self) -> anyhow::Result<u64> {
+        let mut desc = self.clone();
+        desc.render = RenderSettings {
+            samples_per_pixel: 0,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            adaptive: None,
+            ..desc.render
+        };
+        // FNV-1a, which unlike `DefaultHasher` is stable across builds.
+        Ok(desc.to_toml()?.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        }))
+    }
+
+    fn validate(&self, src: &str, spans: &SceneSpans) -> anyhow::Result<()> {
+        let at = |span: Option<Range<usize>>, msg: String| match span {
+            Some(span) => anyhow!("{}: {}", line_of(src, span.start), msg),
+            None => anyhow!("{}", msg),
+        };
+
+        let cam = &self.camera;
+        let cam_span = |key| key_span(Some(&spans.camera), key);
+        if !(cam.aspect_ratio.is_finite() && cam.aspect_ratio > 0.0) {
+            return Err(at(
+                cam_span("aspect_ratio"),
+                "camera aspect_ratio must be positive".into(),
+            ));
+        }
+        if !(cam.vert_fov > 0.0 && cam.vert_fov < 180.0) {
+            return Err(at(
+                cam_span("vert_fov"),
+                "camera vert_fov must be in (0, 180)".into(),
+            ));
+        }
+
+        let (open, close) = cam.time;
+        if !(open.is_finite() && close.is_finite() && open <= close) {
+            return Err(at(
+                cam_span("time"),
+                "camera time must be a finite [open, close] interval".into(),
+            ));
+        }
+
+        let r = &self.render;
+        let render_span = |key| key_span(Some(&spans.render), key);
+        for (key, value) in [
+            ("width", r.width),
+            ("height", r.height),
+            ("samples_per_pixel", r.samples_per_pixel),
+            ("max_scatter_depth", r.max_scatter_depth),
+        ] {
+            if value == 0 {
+                return Err(at(
+                    render_span(key),
+                    format!("render {} must be non-zero", key),
+                ));
+            }
+        }
+        if let Err(msg) = r.integrator.validate() {
+            return Err(at(render_span("integrator"), msg));
+        }
+        if let Some(Err(msg)) = r.adaptive.map(|a| a.validate(r.samples_per_pixel)) {
+            return Err(at(render_span("adaptive"), msg));
+        }
+
+        if let Background::Sky {
+            sun_direction,
+            turbidity,
+            intensity,
+        } = &self.background
+        {
+            let span = |key| key_span(Some(&spans.background), key);
+            if !(sun_direction.y().is_finite() && sun_direction.y() > 0.0) {
+                return Err(at(
+                    span("sun_direction"),
+                    "sky sun_direction must point above the horizon".into(),
+                ));
+            }
+            if !(1.7..=10.0).contains(turbidity) {
+                return Err(at(
+                    span("turbidity"),
+                    "sky turbidity must be in [1.7, 10]".into(),
+                ));
+            }
+            if !(intensity.is_finite() && *intensity >= 0.0) {
+                return Err(at(
+                    span("intensity"),
+                    "sky intensity must be non-negative".into(),
+                ));
+            }
+        }
+        if let Background::Map { intensity, .. } = &self.background {
+            if !(intensity.is_finite() && *intensity >= 0.0) {
+                return Err(at(
+                    key_span(Some(&spans.background), "intensity"),
+                    "map intensity must be non-negative".into(),
+                ));
+            }
+        }
+
+        for (name, mat) in self.materials.iter() {
+            let span = |key| key_span(spans.materials.get(name), key);
+            let colors = match mat {
+                MaterialDesc::Lambertian { albedo } | MaterialDesc::Metal { albedo, .. } => {
+                    Some(("albedo", albedo))
+                }
+                MaterialDesc::DiffuseLight { emit } => Some(("emit", emit)),
+                MaterialDesc::Principled(p) => Some(("base_color", &p.base_color)),
+                MaterialDesc::Dielectric { tint, .. } => Some(("tint", tint)),
+                MaterialDesc::Conductor { .. } => None,
+            };
+            if let Some((key, color)) = colors {
+                if let Err(msg) = color.validate() {
+                    return Err(at(
+                        span(key),
+                        format!("material `{}` {} {}", name, key, msg),
+                    ));
+                }
+            }
+            if let MaterialDesc::Principled(p) = mat {
+                for (key, scalar) in p.scalars() {
+                    if let Err(msg) = scalar.validate() {
+                        return Err(at(
+                            span(key),
+                            format!("material `{}` {} {}", name, key, msg),
+                        ));
+                    }
+                }
+                if !(p.ior.is_finite() && p.ior > 0.0) {
+                    return Err(at(
+                        span("ior"),
+                        format!("material `{}` ior must be positive", name),
+                    ));
+                }
+                let factors = [("metallic", &p.metallic), ("roughness", &p.roughness)];
+                for (key, factor) in factors {
+                    if p.metallic_roughness.is_some()
+                        && matches!(factor, Some(ScalarDesc::Texture(_)))
+                    {
+                        return Err(at(
+                            span(key),
+                            format!(
+                                "material `{}` {} must be a number when metallic_roughness is set",
+                                name, key
+                            ),
+                        ));
+                    }
+                }
+            }
+            match mat {
+                MaterialDesc::Metal { fuzz, .. } if !(0.0..=1.0).contains(fuzz) => {
+                    return Err(at(
+                        span("fuzz"),
+                        format!("material `{}` fuzz must be in [0, 1]", name),
+                    ));
+                }
+                MaterialDesc::Dielectric { ir, .. } if !(ir.is_finite() && *ir > 0.0) => {
+                    return Err(at(
+                        span("ir"),
+                        format!("material `{}` ir must be positive", name),
+                    ));
+                }
+                MaterialDesc::Dielectric { absorption, .. } if !is_non_negative(absorption) => {
+                    return Err(at(
+                        span("absorption"),
+                        format!("material `{}` absorption has a negative component", name),
+                    ));
+                }
+                MaterialDesc::Conductor { preset, eta, k, .. }
+                    if preset.is_some() == (eta.is_some() || k.is_some()) =>
+                {
+                    return Err(at(
+                        span(if preset.is_some() { "preset" } else { "type" }),
+                        format!(
+                            "material `{}` needs either a preset or both eta and k",
+                            name
+                        ),
+                    ));
+                }
+                MaterialDesc::Conductor {
+                    preset: None,
+                    eta,
+                    k,
+                    ..
+                } if eta.is_none() || k.is_none() => {
+                    return Err(at(
+                        span(if eta.is_none() { "k" } else { "eta" }),
+                        format!("material `{}` needs both eta and k", name),
+                    ));
+                }
+                MaterialDesc::Conductor {
+                    eta: Some(eta),
+                    k: Some(k),
+                    ..
+                } if !(is_non_negative(eta) && is_non_negative(k)) => {
+                    return Err(at(
+                        span("eta"),
+                        format!("material `{}` eta and k must be non-negative", name),
+                    ));
+                }
+                _ => {}
+            }
+            if let MaterialDesc::Dielectric {
+                roughness,
+                anisotropy,
+                ..
+            }
+            | MaterialDesc::Conductor {
+                roughness,
+                anisotropy,
+                ..
+            } = mat
+            {
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err(at(
+                        span("roughness"),
+                        format!("material `{}` roughness must be in [0, 1]", name),
+                    ));
+                }
+                if !(-1.0..=1.0).contains(anisotropy) {
+                    return Err(at(
+                        span("anisotropy"),
+                        format!("material `{}` anisotropy must be in [-1, 1]", name),
+                    ));
+                }
+            }
+        }
+
+        for (obj, obj_spans) in self.objects.iter().zip(spans.objects.iter()) {
+            let span = |key| key_span(Some(obj_spans), key);
+            match obj {
+                ObjectDesc::Sphere { radius, .. } | ObjectDesc::MovingSphere { radius, .. }
+                    if *radius == 0.0 || !radius.is_finite() =>
+                {
+                    return Err(at(
+                        span("radius"),
+                        "sphere radius must be finite and non-zero".into(),
+                    ));
+                }
+                ObjectDesc::Quad { u, v, .. } if !Quad::spans_area(u, v) => {
+                    return Err(at(
+                        span("v"),
+                        "quad edges u and v must not be parallel or zero".into(),
+                    ));
+                }
+                _ => {}
+            }
+
+            if let ObjectDesc::Obj { transform, .. } = obj {
+                if transform.to_transform().is_none() {
+                    return Err(at(
+                        span("transform"),
+                        "transform scale must be finite and non-zero on every axis".into(),
+                    ));
+                }
+            }
+
+            let material = obj.material();
+            if !self.materials.contains_key(material) {
+                return Err(at(
+                    span("material"),
+                    format!("unknown material `{}`", material),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the scene geometry; relative OBJ, texture and environment map
+    /// paths resolve against `base_dir`.
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Scene> {
+        let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+        for (name, desc) in self.materials.iter() {
+            let m: Arc<dyn Material> = match desc {
+                MaterialDesc::Lambertian { albedo } => {
+                    Arc::new(Lambertian::textured(albedo.build(base_dir)?))
+                }
+                MaterialDesc::Metal { albedo, fuzz } => {
+                    Arc::new(Metal::textured(albedo.build(base_dir)?, *fuzz))
+                }
+                MaterialDesc::Dielectric {
+                    ir,
+                    tint,
+                    absorption,
+                    fresnel,
+                    roughness: 0.0,
+                    ..
+                } => Arc::new(
+                    Dielectric::new(*ir)
+                        .with_tint_texture(tint.build(base_dir)?)
+                        .with_absorption(*absorption)
+                        .with_fresnel(*fresnel),
+                ),
+                MaterialDesc::Dielectric {
+                    ir,
+                    tint,
+                    absorption,
+                    fresnel,
+                    roughness,
+                    anisotropy,
+                } => Arc::new(
+                    RoughDielectric::new(*ir, Ggx::from_roughness(*roughness, *anisotropy))
+                        .with_tint_texture(tint.build(base_dir)?)
+                        .with_absorption(*absorption)
+                        .with_fresnel(*fresnel),
+                ),
+                MaterialDesc::Conductor {
+                    preset,
+                    eta,
+                    k,
+                    roughness,
+                    anisotropy,
+                } => {
+                    let ior = match (preset, eta, k) {
+                        (Some(preset), _, _) => preset.ior(),
+                        (None, Some(eta), Some(k)) => ComplexIor { eta: *eta, k: *k },
+                        _ => bail!(
+                            "material `{}` needs either a preset or both eta and k",
+                            name
+                        ),
+                    };
+                    Arc::new(Conductor::new(
+                        ior,
+                        Ggx::from_roughness(*roughness, *anisotropy),
+                    ))
+                }
+                MaterialDesc::DiffuseLight { emit } => {
+                    Arc::new(DiffuseLight::textured(emit.build(base_dir)?))
+                }
+                MaterialDesc::Principled(p) => Arc::new(p.build(base_dir)?),
+            };
+            materials.insert(name.as_str(), m);
+        }
+        let material = |name: &str| {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("unknown material `{}`", name))
+        };
+
+        let mut objects: HitList = HitList::new();
+        // Emissive shapes that can be sampled directly, for next-event estimation.
+        let mut lights: HitList = HitList::new();
+        // Loaded OBJ models by file and default material, for instancing.
+        let mut models: BTreeMap<(&Path, &str), Arc<DynHittable>> = BTreeMap::new();
+        for obj in self.objects.iter() {
+            let object: Arc<DynHittable> = match obj {
+                ObjectDesc::Sphere {
+                    center,
+                    radius,
+                    material: m,
+                } => Arc::new(Sphere::new(material(m)?, *center, *radius)),
+                ObjectDesc::MovingSphere {
+                    center0,
+                    center1,
+                    time0,
+                    time1,
+                    radius,
+                    material: m,
+                } => Arc::new(MovingSphere::new(
+                    material(m)?,
+                    *center0,
+                    *center1,
+                    *time0,
+                    *time1,
+                    *radius,
+                )),
+                ObjectDesc::Triangle {
+                    vertices: [v0, v1, v2],
+                    material: m,
+                } => Arc::new(Triangle::new(material(m)?, *v0, *v1, *v2)),
+                ObjectDesc::Quad {
+                    q,
+                    u,
+                    v,
+                    material: m,
+                } => Arc::new(Quad::new(material(m)?, *q, *u, *v)?),
+                ObjectDesc::Obj {
+                    path,
+                    material: m,
+                    transform,
+                } => {
+                    let key = (path.as_path(), m.as_str());
+                    let model = match models.get(&key) {
+                        Some(model) => Arc::clone(model),
+                        None => {
+                            let model = load_obj(&base_dir.join(path), material(m)?)?;
+                            let groups: Vec<Arc<DynHittable>> = model
+                                .groups
+                                .into_iter()
+                                .map(|g| g.mesh as Arc<DynHittable>)
+                                .collect();
+                            let model: Arc<DynHittable> = Arc::new(Bvh::new(groups, 0.0, 0.0));
+                            models.insert(key, Arc::clone(&model));
+                            model
+                        }
+                    };
+                    if transform.is_identity() {
+                        model
+                    } else {
+                        let transform = transform.to_transform().ok_or_else(|| {
+                            anyhow!("transform scale must be finite and non-zero on every axis")
+                        })?;
+                        Arc::new(Instance::new(model, transform))
+                    }
+                }
+            };
+            let emissive = matches!(
+                self.materials.get(obj.material()),
+                Some(MaterialDesc::DiffuseLight { .. })
+            );
+            let samplable = matches!(
+                obj,
+                ObjectDesc::Sphere { .. } | ObjectDesc::Triangle { .. } | ObjectDesc::Quad { .. }
+            );
+            if emissive && samplable {
+                lights.push(Arc::clone(&object));
+            }
+            objects.push(object);
+        }
+
+        let mut camera = self.camera;
+        camera.samples_per_pixel = self.render.samples_per_pixel;
+        camera.max_scatter_depth = self.render.max_scatter_depth;
+        camera.roulette_depth = self.render.roulette_depth;
+        camera.seed = self.render.seed;
+        camera.sampler = self.render.sampler;
+
+        let (time0, time1) = camera.time;
+        Ok(Scene {
+            camera,
+            size: RectSize {
+                width: self.render.width,
+                height: self.render.height,
+            },
+            world: World::new(Arc::new(Bvh::from_list(&objects, time0, time1)))
+                .with_lights(lights)
+                .with_environment(self.background.build(base_dir)?),
+            integrator: self.render.integrator,
+            adaptive: self.render.adaptive,
+        })
+    }
+}
+
+impl Scene {
+    /// Loads, validates and builds a scene file in one step.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let desc = SceneDesc::load(path)?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        desc.build(base_dir)
+            .map_err(|e| anyhow!("{}: {:#}", path.display(), e))
+    }
+}
+
+fn line_of(src: &str, offset: usize) -> usize {
+    src[..offset.min(src.len())].matches('\n').count() + 1
+}
+
+fn toml_error(src: &str, err: &toml::de::Error) -> anyhow::Error {
+    match err.span() {
+        Some(span) => anyhow!("{}: {}", line_of(src, span.start), err.message()),
+        None => anyhow!("{}", err.message()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"
+[materials.gold]
+type = "metal"
+albedo = [0.8, 0.6, 0.2]
+fuzz = 0.1
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = "gold"
+"#;
+
+    fn parse_err(src: &str) -> String {
+        match SceneDesc::parse(src) {
+            Ok(_) => panic!("invalid scene accepted"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn valid_scene_round_trips() {
+        let desc = SceneDesc::parse(SCENE).unwrap();
+        let again = SceneDesc::parse(&desc.to_toml().unwrap()).unwrap();
+        assert_eq!(again.materials.len(), 1);
+        assert_eq!(again.objects.len(), 1);
+        assert_eq!(again.objects[0].material(), "gold");
+    }
+
+    #[test]
+    fn unknown_material_is_reported_at_its_line() {
+        let src = SCENE.replace("material = \"gold\"", "material = \"silver\"");
+        assert_eq!(parse_err(&src), "11: unknown material `silver`");
+    }
+
+    #[test]
+    fn out_of_range_value_is_reported_at_its_line() {
+        let src = SCENE.replace("fuzz = 0.1", "fuzz = 1.5");
+        assert_eq!(parse_err(&src), "5: material `gold` fuzz must be in [0, 1]");
+
+        let src = format!("[camera]\nvert_fov = 180.0\n{}", SCENE);
+        assert_eq!(parse_err(&src), "2: camera vert_fov must be in (0, 180)");
+
+        let src = format!(
+            "[render]\nsamples_per_pixel = 8\nadaptive = {{ min_samples = 16 }}\n{}",
+            SCENE
+        );
+        assert_eq!(
+            parse_err(&src),
+            "3: adaptive min_samples (16) must not exceed samples_per_pixel (8)"
+        );
+    }
+
+    #[test]
+    fn zero_scale_is_reported_at_its_line() {
+        let src = format!(
+            "{}\n[[objects]]\ntype = \"obj\"\npath = \"model.obj\"\nmaterial = \"gold\"\ntransform = {{ scale = [1.0, 0.0, 1.0] }}\n",
+            SCENE
+        );
+        assert_eq!(
+            parse_err(&src),
+            "17: transform scale must be finite and non-zero on every axis"
+        );
+    }
+
+    #[test]
+    fn degenerate_quad_is_reported_at_its_line() {
+        let src = format!(
+            "{}\n[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [2.0, 0.0, 0.0]\nmaterial = \"gold\"\n",
+            SCENE
+        );
+        assert_eq!(
+            parse_err(&src),
+            "17: quad edges u and v must not be parallel or zero"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    perlin::Perlin,
+    vec::{Color, Vec3},
+};
+
+/// A colour that varies over a surface, looked up by surface coordinates
+/// `(u, v)` or by the hit point itself for solid (3D) textures.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color;
+}
+
+pub struct SolidColor(pub Color);
+
+impl SolidColor {
+    pub const fn new(color: Color) -> Self {
+        Self(color)
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Vec3) -> Color {
+        self.0
+    }
+}
+
+/// Alternates between two textures in a 3D grid of cubes of side `scale`.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(scale, Arc::new(SolidColor(even)), Arc::new(SolidColor(odd)))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let cell = |c: f64| (c * self.inv_scale).floor() as i64;
+        let sum = cell(point.x()) + cell(point.y()) + cell(point.z());
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+/// Image mapped onto the surface by its `(u, v)` coordinates, with `v = 0`
+/// at the bottom row. Lookups are nearest-texel and clamp at the edges.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::load_with(path, true)
+    }
+
+    /// Loads an image of non-colour data such as roughness or metalness,
+    /// whose values are used as stored rather than sRGB decoded.
+    pub fn load_data(path: &Path) -> anyhow::Result<Self> {
+        Self::load_with(path, false)
+    }
+
+    fn load_with(path: &Path, srgb: bool) -> anyhow::Result<Self> {
+        let (width, height, pixels) = load_rgb(path, srgb)
+            .with_context(|| format!("failed to load texture {}", path.display()))?;
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Vec3) -> Color {
+        if self.pixels.is_empty() {
+            // Debug cyan, so missing data stands out.
+            return Vec3(0.0, 1.0, 1.0);
+        }
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// A colour channel, for reading scalar parameters out of colour textures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    R,
+    G,
+    B,
+}
+
+/// One channel of another texture, repeated across all three. glTF packs
+/// roughness into green and metalness into blue of one image, for example.
+pub struct ChannelTexture {
+    texture: Arc<dyn Texture>,
+    channel: Channel,
+}
+
+impl ChannelTexture {
+    pub fn new(texture: Arc<dyn Texture>, channel: Channel) -> Self {
+        Self { texture, channel }
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let c = self.texture.value(u, v, point);
+        let x = match self.channel {
+            Channel::R => c.x(),
+            Channel::G => c.y(),
+            Channel::B => c.z(),
+        };
+        Vec3(x, x, x)
+    }
+}
+
+/// Another texture multiplied by a constant factor.
+pub struct ScaledTexture {
+    texture: Arc<dyn Texture>,
+    factor: Color,
+}
+
+impl ScaledTexture {
+    pub fn new(texture: Arc<dyn Texture>, factor: Color) -> Self {
+        Self { texture, factor }
+    }
+}
+
+impl Texture for ScaledTexture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        self.texture.value(u, v, point) * self.factor
+    }
+}
+
+/// How `NoiseTexture` turns Perlin noise into a colour.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseStyle {
+    /// Plain smooth noise.
+    #[default]
+    Perlin,
+    /// Several octaves summed, giving a cloudy look.
+    Turbulence,
+    /// Sine stripes along z, phase-shifted by turbulence.
+    Marble,
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    style: NoiseStyle,
+    color: Color,
+}
+
+impl NoiseTexture {
+    /// `scale` is the noise frequency; `color` is modulated by the noise.
+    pub fn new(seed: u64, scale: f64, style: NoiseStyle, color: Color) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            style,
+            color,
+        }
+    }
+}
+
+const TURBULENCE_DEPTH: u32 = 7;
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Vec3) -> Color {
+        let p = point.mul_scalar(self.scale);
+        let t = match self.style {
+            NoiseStyle::Perlin => 0.5 * (1.0 + self.noise.noise(&p)),
+            NoiseStyle::Turbulence => self.noise.turbulence(&p, TURBULENCE_DEPTH),
+            NoiseStyle::Marble => {
+                0.5 * (1.0
+                    + f64::sin(p.z() + 10.0 * self.noise.turbulence(point, TURBULENCE_DEPTH)))
+            }
+        };
+        self.color.mul_scalar(t)
+    }
+}
+
+/// Loads an image as linear RGB, row-major from the top. HDR data is used
+/// as-is; 8/16-bit images are assumed to be sRGB encoded and linearized.
+pub(crate) fn load_linear_rgb(path: &Path) -> anyhow::Result<(usize, usize, Vec<Color>)> {
+    load_rgb(path, true)
+}
+
+/// Like `load_linear_rgb`, but 8/16-bit images are only sRGB decoded if
+/// `srgb` is set.
+fn load_rgb(path: &Path, srgb: bool) -> anyhow::Result<(usize, usize, Vec<Color>)> {
+    let image = image::open(path)?;
+    let is_float = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let rgb = image.into_rgb32f();
+    let decode = |c: f32| {
+        let c = c as f64;
+        if is_float || !srgb {
+            c
+        } else {
+            srgb_to_linear(c)
+        }
+    };
+    let pixels = rgb
+        .pixels()
+        .map(|p| Vec3(decode(p[0]), decode(p[1]), decode(p[2])))
+        .collect();
+    Ok((rgb.width() as usize, rgb.height() as usize, pixels))
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
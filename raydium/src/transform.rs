@@ -0,0 +1,448 @@
+use std::ops::Mul;
+
+use crate::{aabb::Aabb, math::radians, vec::Vec3};
+
+/// Row-major 4x4 matrix acting on column vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub [[f64; 4]; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(t: &Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[0][3] = t.x();
+        m.0[1][3] = t.y();
+        m.0[2][3] = t.z();
+        m
+    }
+
+    pub fn scale(s: &Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[0][0] = s.x();
+        m.0[1][1] = s.y();
+        m.0[2][2] = s.z();
+        m
+    }
+
+    pub fn rotation(q: &Quat) -> Self {
+        let Quat { w, x, y, z } = q.normalize();
+        Mat4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = Self::IDENTITY;
+        for (i, row) in self.0.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                t.0[j][i] = *v;
+            }
+        }
+        t
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting. `None` if singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inv = Self::IDENTITY.0;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4(inv))
+    }
+
+    /// Determinant of the upper-left 3x3 block: how much the matrix scales
+    /// volumes, ignoring translation.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Applies the matrix to a point (w = 1). Affine matrices only.
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    /// Applies the matrix to a direction (w = 0), ignoring translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Mat4(m)
+    }
+}
+
+/// Rotation quaternion `w + xi + yj + zk`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Rotation by `angle` radians counter-clockwise about `axis`.
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Self {
+        let a = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Self {
+            w: cos,
+            x: a.x() * sin,
+            y: a.y() * sin,
+            z: a.z() * sin,
+        }
+    }
+
+    /// Rotation about x, then y, then z, by the given angles in degrees.
+    pub fn from_euler_degrees(angles: &Vec3) -> Self {
+        let qx = Self::from_axis_angle(&Vec3(1.0, 0.0, 0.0), radians(angles.x()));
+        let qy = Self::from_axis_angle(&Vec3(0.0, 1.0, 0.0), radians(angles.y()));
+        let qz = Self::from_axis_angle(&Vec3(0.0, 0.0, 1.0), radians(angles.z()));
+        qz * qy * qx
+    }
+
+    pub fn dot(&self, other: &Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.dot(self).sqrt();
+        Self {
+            w: self.w / len,
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+        }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn rotate(&self, v: &Vec3) -> Vec3 {
+        let p = Quat {
+            w: 0.0,
+            x: v.x(),
+            y: v.y(),
+            z: v.z(),
+        };
+        let r = *self * p * self.conjugate();
+        Vec3(r.x, r.y, r.z)
+    }
+
+    /// Spherical linear interpolation along the shorter arc.
+    pub fn slerp(&self, other: &Self, t: f64) -> Self {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            other = Quat {
+                w: -other.w,
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+            };
+            cos = -cos;
+        }
+        let (a, b) = if cos > 0.9995 {
+            // Nearly parallel: fall back to lerp to avoid dividing by ~0.
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }
+        .normalize()
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, r: Self) -> Self::Output {
+        Quat {
+            w: self.w * r.w - self.x * r.x - self.y * r.y - self.z * r.z,
+            x: self.w * r.x + self.x * r.w + self.y * r.z - self.z * r.y,
+            y: self.w * r.y - self.x * r.z + self.y * r.w + self.z * r.x,
+            z: self.w * r.z + self.x * r.y - self.y * r.x + self.z * r.w,
+        }
+    }
+}
+
+/// Invertible affine transform from object to world space, with its inverse
+/// kept alongside so rays can be taken the other way cheaply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: Mat4::IDENTITY,
+        inverse: Mat4::IDENTITY,
+    };
+
+    /// `None` if `matrix` is singular.
+    pub fn new(matrix: Mat4) -> Option<Self> {
+        Some(Self {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn translate(t: &Vec3) -> Self {
+        Self {
+            matrix: Mat4::translation(t),
+            inverse: Mat4::translation(&t.mul_scalar(-1.0)),
+        }
+    }
+
+    pub fn rotate(q: &Quat) -> Self {
+        let matrix = Mat4::rotation(q);
+        Self {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    /// `None` if any component of `s` is zero or not finite.
+    pub fn scale(s: &Vec3) -> Option<Self> {
+        let invertible = |c: f64| c != 0.0 && c.is_finite();
+        if !(invertible(s.x()) && invertible(s.y()) && invertible(s.z())) {
+            return None;
+        }
+        Some(Self {
+            matrix: Mat4::scale(s),
+            inverse: Mat4::scale(&Vec3(1.0 / s.x(), 1.0 / s.y(), 1.0 / s.z())),
+        })
+    }
+
+    /// Scale, then rotate, then translate. `None` if the scale can't be
+    /// inverted.
+    pub fn from_trs(translation: &Vec3, rotation: &Quat, scale: &Vec3) -> Option<Self> {
+        Some(
+            Self::scale(scale)?
+                .then(&Self::rotate(rotation))
+                .then(&Self::translate(translation)),
+        )
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub const fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.matrix == Mat4::IDENTITY
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    /// Normals transform by the inverse transpose to stay perpendicular to
+    /// the surface under non-uniform scale. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    /// Bounds of the eight transformed corners of `b`.
+    pub fn bounds(&self, b: &Aabb) -> Aabb {
+        let corner = |i: usize| {
+            Vec3(
+                if i & 1 == 0 { b.min.x() } else { b.max.x() },
+                if i & 2 == 0 { b.min.y() } else { b.max.y() },
+                if i & 4 == 0 { b.min.z() } else { b.max.z() },
+            )
+        };
+        let first = self.point(&corner(0));
+        (1..8).fold(Aabb::new(first, first), |acc, i| {
+            acc.grow(&self.point(&corner(i)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn assert_identity(m: &Mat4) {
+        for (i, row) in m.0.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((v - expected).abs() < 1e-12, "{:?} is not the identity", m);
+            }
+        }
+    }
+
+    fn trs() -> Transform {
+        Transform::from_trs(
+            &Vec3(1.0, -2.0, 3.0),
+            &Quat::from_euler_degrees(&Vec3(30.0, 45.0, -60.0)),
+            &Vec3(2.0, 0.5, -3.0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn matrices_times_their_inverse_are_the_identity() {
+        let t = trs();
+        assert_identity(&(*t.matrix() * *t.inverse().matrix()));
+        assert_identity(&(*t.inverse().matrix() * *t.matrix()));
+
+        // The general inverse agrees with the one built up piecewise.
+        let m = t.matrix().inverse().unwrap();
+        assert_identity(&(m * *t.matrix()));
+
+        let skew = Mat4([
+            [1.0, 2.0, 0.0, 4.0],
+            [0.0, 1.0, 3.0, -1.0],
+            [2.0, 0.0, 1.0, 0.5],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_identity(&(skew * skew.inverse().unwrap()));
+        assert!(Mat4::scale(&Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn zero_or_non_finite_scale_is_rejected() {
+        assert!(Transform::scale(&Vec3(1.0, 0.0, 1.0)).is_none());
+        assert!(Transform::scale(&Vec3(f64::INFINITY, 1.0, 1.0)).is_none());
+        assert!(Transform::scale(&Vec3(1.0, 1.0, f64::NAN)).is_none());
+        assert!(Transform::from_trs(&Vec3::zero(), &Quat::IDENTITY, &Vec3::zero()).is_none());
+        assert!(Transform::scale(&Vec3(-1.0, 2.0, 0.5)).is_some());
+    }
+
+    #[test]
+    fn rotation_follows_the_right_hand_rule() {
+        let q = Quat::from_axis_angle(&Vec3(0.0, 0.0, 1.0), PI / 2.0);
+        let p = Transform::rotate(&q).point(&Vec3(1.0, 0.0, 0.0));
+        assert!((p - Vec3(0.0, 1.0, 0.0)).len() < 1e-12);
+        let back = Transform::rotate(&q).inverse().point(&p);
+        assert!((back - Vec3(1.0, 0.0, 0.0)).len() < 1e-12);
+    }
+}
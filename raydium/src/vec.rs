@@ -1,21 +1,23 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 
-use rand::{Rng, SeedableRng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-type Vec3_X = f64;
-type Vec3_Y = f64;
-type Vec3_Z = f64;
+use crate::sampler::{sample_disk, Sampler};
 
-#[derive(Debug, Copy, Clone, Default)]
-pub struct Vec3(pub Vec3_X, pub Vec3_Y, pub Vec3_Y);
+type Vec3X = f64;
+type Vec3Y = f64;
+type Vec3Z = f64;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Vec3(pub Vec3X, pub Vec3Y, pub Vec3Z);
 
 impl Vec3 {
-    pub fn new_rand() -> Self {
-        Self::new_rand_range(0.0, 1.0)
+    pub fn new_rand<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::new_rand_range(rng, 0.0, 1.0)
     }
 
-    pub fn new_rand_range(min: f64, max: f64) -> Self {
-        let mut rng = rand::rngs::StdRng::from_entropy();
+    pub fn new_rand_range<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
         let range = min..max;
 
         Self(
@@ -25,31 +27,30 @@ impl Vec3 {
         )
     }
 
-    pub fn new_rand_unit_vector() -> Self {
-        Self::new_rand_unit_sphere().normalize()
+    pub fn new_rand_unit_vector(sampler: &mut dyn Sampler) -> Self {
+        Self::new_rand_unit_sphere(sampler).normalize()
     }
 
-    pub fn new_rand_unit_sphere() -> Self {
-        loop {
-            let p = Self::new_rand_range(-1.0, 1.0);
-            if p.len_sq() >= 1.0 {
-                continue;
-            } else {
-                return p.normalize();
-            }
-        }
+    /// Uniform direction on the unit sphere.
+    pub fn new_rand_unit_sphere(sampler: &mut dyn Sampler) -> Self {
+        let (r1, r2) = sampler.next_2d();
+        let z = 1.0 - 2.0 * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * r2;
+        Self(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn new_rand_in_unit_disk() -> Self {
-        let mut rng = rand::rngs::StdRng::from_entropy();
-        loop {
-            let p = Vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-            if p.len_sq() < 1.0 {
-                break p;
-            } else {
-                continue;
-            }
-        }
+    /// Random direction about +z with density cos(theta) / pi.
+    pub fn new_rand_cosine_direction(sampler: &mut dyn Sampler) -> Self {
+        let (r1, r2) = sampler.next_2d();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let r = r2.sqrt();
+        Self(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+    }
+
+    pub fn new_rand_in_unit_disk(sampler: &mut dyn Sampler) -> Self {
+        let (x, y) = sample_disk(sampler.next_2d());
+        Self(x, y, 0.0)
     }
 
     pub const fn zero() -> Self {
@@ -102,10 +103,35 @@ impl Vec3 {
         )
     }
 
+    pub fn min(&self, other: &Self) -> Self {
+        Self(
+            f64::min(self.x(), other.x()),
+            f64::min(self.y(), other.y()),
+            f64::min(self.z(), other.z()),
+        )
+    }
+
+    pub fn max(&self, other: &Self) -> Self {
+        Self(
+            f64::max(self.x(), other.x()),
+            f64::max(self.y(), other.y()),
+            f64::max(self.z(), other.z()),
+        )
+    }
+
     pub fn normalize(&self) -> Self {
         self.div_scalar(self.len())
     }
 
+    pub fn max_component(&self) -> f64 {
+        self.0.max(self.1).max(self.2)
+    }
+
+    /// Relative luminance of a linear Rec. 709 colour.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
     pub fn is_near_zero(&self) -> bool {
         const EPSILON: f64 = 1e-8;
         self.x().abs() < EPSILON && self.y().abs() < EPSILON && self.z().abs() < EPSILON
@@ -144,6 +170,19 @@ impl Mul for Vec3 {
     }
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl Neg for Vec3 {
     type Output = Self;
 
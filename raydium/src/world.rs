@@ -1,8 +1,64 @@
-use std::ops::Neg;
+use std::{cell::Cell, sync::Arc};
 
-use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::{math::radians, ray::Ray, render::defaults, vec::Vec3};
+use crate::{
+    aabb::Aabb,
+    environment::{Environment, GradientSky},
+    math::radians,
+    ray::{DynHittable, HitList, HitRecord, Ray},
+    render::defaults,
+    sampler::{Sampler, SamplerKind},
+    vec::Vec3,
+};
+
+/// Everything a ray can interact with: the scene objects and the environment
+/// surrounding them. `lights` repeats the emissive objects that can be
+/// sampled directly; they must also be in `objects` to be seen.
+#[derive(Clone)]
+pub struct World {
+    pub objects: Arc<DynHittable>,
+    pub lights: Arc<HitList>,
+    pub environment: Arc<dyn Environment>,
+}
+
+impl World {
+    pub fn new(objects: Arc<DynHittable>) -> Self {
+        Self {
+            objects,
+            lights: Arc::new(HitList::new()),
+            environment: Arc::new(GradientSky::default()),
+        }
+    }
+
+    pub fn with_lights(mut self, lights: HitList) -> Self {
+        self.lights = Arc::new(lights);
+        self
+    }
+
+    pub fn with_environment(mut self, environment: Arc<dyn Environment>) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Closest object hit along `ray` within `t_min..t_max`, counted in
+    /// `rays_traced`.
+    pub fn trace(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        RAYS_TRACED.with(|n| n.set(n.get() + 1));
+        self.objects.hit(ray, t_min, t_max)
+    }
+}
+
+thread_local! {
+    static RAYS_TRACED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Rays the current thread has traced through any `World` so far. Kept per
+/// thread so that counting costs workers no synchronisation; take the
+/// difference across a stretch of work to count the rays it traced.
+pub fn rays_traced() -> u64 {
+    RAYS_TRACED.with(Cell::get)
+}
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Camera {
@@ -18,7 +74,8 @@ pub struct Camera {
     info: CameraInfo,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CameraInfo {
     pub look_from: Vec3,
     pub look_at: Vec3,
@@ -28,8 +85,18 @@ pub struct CameraInfo {
     pub aperture: f64,
     pub focus_dist: f64,
     pub time: (f64, f64),
+    // Scene files keep these under [render] rather than [camera].
+    #[serde(skip)]
     pub max_scatter_depth: u32,
+    /// Bounces every path takes before Russian roulette may end it.
+    #[serde(skip)]
+    pub roulette_depth: u32,
+    #[serde(skip)]
     pub samples_per_pixel: u32,
+    #[serde(skip)]
+    pub seed: u64,
+    #[serde(skip)]
+    pub sampler: SamplerKind,
 }
 
 impl Default for CameraInfo {
@@ -44,11 +111,40 @@ impl Default for CameraInfo {
             focus_dist: 10.,
             time: (0., 0.),
             max_scatter_depth: defaults::MAX_SCATTER_DEPTH,
+            roulette_depth: defaults::ROULETTE_DEPTH,
             samples_per_pixel: defaults::NUM_SAMPLES,
+            seed: 0,
+            sampler: SamplerKind::default(),
         }
     }
 }
 
+impl CameraInfo {
+    /// Points the camera at the center of `bounds` and backs it off along the
+    /// current view direction until the bounds' enclosing sphere fits in frame.
+    pub fn frame_bounds(&mut self, bounds: &Aabb) {
+        let center = bounds.centroid();
+        let radius = bounds.extent().len() / 2.0;
+
+        let view_dir = {
+            let d = self.look_from - self.look_at;
+            if d.is_near_zero() {
+                Vec3(0., 0., 1.)
+            } else {
+                d.normalize()
+            }
+        };
+
+        let half_vfov = radians(self.vert_fov) / 2.0;
+        let half_hfov = f64::atan(f64::tan(half_vfov) * self.aspect_ratio);
+        let dist = radius / f64::sin(f64::min(half_vfov, half_hfov));
+
+        self.look_at = center;
+        self.look_from = center + view_dir.mul_scalar(dist);
+        self.focus_dist = dist;
+    }
+}
+
 impl Camera {
     pub const fn origin(&self) -> Vec3 {
         self.origin
@@ -59,13 +155,29 @@ impl Camera {
     pub const fn lens_radius(&self) -> f64 {
         self.lens_radius
     }
+    pub const fn uvw(&self) -> (Vec3, Vec3, Vec3) {
+        (self.u, self.v, self.w)
+    }
+    pub const fn time(&self) -> (f64, f64) {
+        self.time
+    }
     pub const fn max_scatter_depth(&self) -> u32 {
         self.info.max_scatter_depth
     }
+    pub const fn roulette_depth(&self) -> u32 {
+        self.info.roulette_depth
+    }
     pub const fn samples_per_pixel(&self) -> u32 {
         self.info.samples_per_pixel
     }
+    pub const fn seed(&self) -> u64 {
+        self.info.seed
+    }
+    pub const fn sampler(&self) -> SamplerKind {
+        self.info.sampler
+    }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -88,7 +200,10 @@ impl Camera {
             time: time.unwrap_or_default(),
             focus_dist,
             max_scatter_depth,
+            roulette_depth: defaults::ROULETTE_DEPTH,
             samples_per_pixel,
+            seed: 0,
+            sampler: SamplerKind::default(),
         })
     }
     pub fn with_info(info: &CameraInfo) -> Self {
@@ -102,7 +217,7 @@ impl Camera {
             focus_dist,
             time,
             ..
-        } = info.clone();
+        } = *info;
 
         let theta = radians(vert_fov);
         let h = f64::tan(theta / 2.0);
@@ -132,16 +247,22 @@ impl Camera {
             w,
             lens_radius,
             time,
-            info: info.clone(),
+            info: *info,
         }
     }
 
-    pub fn cast_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = Vec3::new_rand_in_unit_disk().mul_scalar(self.lens_radius);
+    pub fn cast_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = Vec3::new_rand_in_unit_disk(sampler).mul_scalar(self.lens_radius);
         let offset = self.u.mul_scalar(rd.x()) + self.v.mul_scalar(rd.y());
         let direction = self.pixel00 + self.horizontal.mul_scalar(u) + self.vertical.mul_scalar(v)
             - self.origin
             - offset;
-        Ray::new(self.origin + offset, direction)
+        let (time0, time1) = self.time;
+        let time = if time1 > time0 {
+            time0 + sampler.next_1d() * (time1 - time0)
+        } else {
+            time0
+        };
+        Ray::new_timed(self.origin + offset, direction, time)
     }
 }